@@ -60,6 +60,10 @@ public class RustPlayer extends Connect4ArenaMain.DefaultPlayer {
             case RED -> 0;
         };
         byte[] boardBuf = RustPlayer.encodeBoard(this.board);
-        return RustPlayer.rustPlay(player, boardBuf);
+        int move = RustPlayer.rustPlay(player, boardBuf);
+        if (move == -1) {
+            throw new IllegalStateException("it's not the turn of " + this.myColor + " on this board");
+        }
+        return move;
     }
 }
\ No newline at end of file
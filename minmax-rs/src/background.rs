@@ -0,0 +1,183 @@
+//! Searching on another thread, so that the caller can keep reacting to the user
+//! and stop the search when the move isn't needed anymore.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use crate::{state::IgnorePlayer, Game, PerfectPlayer, Score};
+
+/// Stops a search from another thread. All clones stop the same search.
+#[derive(Debug, Clone, Default)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The search notices this after at most a few thousand nodes and makes the best move it has.
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Allows searching with this handle again.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+type BestSoFar<G> = Arc<Mutex<Option<(<G as Game>::Move, Score<IgnorePlayer>)>>>;
+
+/// A [`PerfectPlayer`] thinking about a move on its own thread.
+/// Created with [`PerfectPlayer::search_in_background`].
+pub struct BackgroundSearch<G: Game> {
+    stop: StopHandle,
+    best_so_far: BestSoFar<G>,
+    thread: JoinHandle<PerfectPlayer<G>>,
+}
+
+impl<G: Game + 'static> PerfectPlayer<G> {
+    /// Starts searching the board on a new thread. The search deepens iteratively until it reaches
+    /// the max depth or the time limit, or until it's stopped, and it always knows a move to make.
+    ///
+    /// The player comes back with [`BackgroundSearch::join`], keeping its transposition table.
+    pub fn search_in_background(self, board: &G) -> BackgroundSearch<G> {
+        let deadline = self
+            .time_limit()
+            .map(|time_limit| Instant::now() + time_limit);
+        BackgroundSearch::start(self, board, deadline)
+    }
+}
+
+impl<G: Game + 'static> BackgroundSearch<G> {
+    pub(crate) fn start(player: PerfectPlayer<G>, board: &G, deadline: Option<Instant>) -> Self {
+        let stop = StopHandle::new();
+        let best_so_far: BestSoFar<G> = Arc::default();
+
+        // The player gets its own handle back afterwards, stopping this search shouldn't stop the next one.
+        let own_stop = player.stop_handle();
+        let mut player = player.with_stop_handle(stop.clone());
+        let mut board = board.clone();
+        let report_to = Arc::clone(&best_so_far);
+
+        let thread = thread::spawn(move || {
+            player.search_until(&mut board, deadline, |pos, score| {
+                *report_to.lock().unwrap() = Some((pos, score));
+            });
+            player.with_stop_handle(own_stop)
+        });
+
+        Self {
+            stop,
+            best_so_far,
+            thread,
+        }
+    }
+}
+
+impl<G: Game> BackgroundSearch<G> {
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// The best move of the deepest search that finished, with its score.
+    /// `None` if not even the first one did yet.
+    pub fn best_so_far(&self) -> Option<(G::Move, Score<IgnorePlayer>)> {
+        *self.best_so_far.lock().unwrap()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Waits for the search to finish. Afterwards, [`PerfectPlayer::best_move`] is the move it found.
+    pub fn join(self) -> PerfectPlayer<G> {
+        self.thread.join().expect("search thread panicked")
+    }
+
+    /// Stops the search and waits for it.
+    pub fn stop(self) -> PerfectPlayer<G> {
+        self.stop.stop();
+        self.join()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::connect4::board::Connect4;
+    use crate::tic_tac_toe::TicTacToe;
+    use crate::{Game, GamePlayer, PerfectPlayer, Score};
+
+    use super::StopHandle;
+
+    #[test]
+    fn stop_keeps_best_move_so_far() {
+        let board = Connect4::empty();
+        let search = PerfectPlayer::new(false)
+            .with_max_depth(None)
+            .search_in_background(&board);
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(!search.is_finished());
+        let (best_so_far, _) = search.best_so_far().expect("not even depth 1 finished");
+
+        let start = Instant::now();
+        let player = search.stop();
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        // The stopped iteration doesn't count, but a deeper one may have finished in between.
+        assert!(board.possible_moves().any(|pos| pos == best_so_far));
+        assert!(board
+            .possible_moves()
+            .any(|pos| pos == player.best_move(&board)));
+    }
+
+    #[test]
+    fn finishes_on_its_own() {
+        let board = TicTacToe::empty();
+        let search = PerfectPlayer::new(false).search_in_background(&board);
+
+        while !search.is_finished() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            search.best_so_far().map(|(_, score)| score),
+            Some(Score::TIE)
+        );
+        search.join();
+    }
+
+    #[test]
+    fn stop_handle_stops_next_move() {
+        let stop = StopHandle::new();
+        let mut player = PerfectPlayer::new(false)
+            .with_max_depth(None)
+            .with_stop_handle(stop.clone());
+
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            stop.stop();
+        });
+
+        let board = Connect4::empty();
+        let pos = player.next_move(&board);
+        stopper.join().unwrap();
+
+        assert!(board.possible_moves().any(|legal| legal == pos));
+    }
+}
@@ -1,17 +1,51 @@
 use std::{
+    borrow::Cow,
     fmt::{Display, Write},
     ops::{Index, IndexMut},
 };
 
 use crate::{
+    notation,
     state::{position_as_int, IgnorePlayer, Position},
-    Game, Player, Score, State,
+    Game, MoveError, ParseError, Player, Score, State,
 };
 
-const WIDTH: usize = 7;
+pub(super) const WIDTH: usize = 7;
 const HEIGTH: usize = 4;
 const BOARD_POSITIONS: usize = WIDTH * HEIGTH;
 
+/// One random key for every player on every position. The hash of the board is all keys
+/// of the occupied positions XORed together, so it can be updated with a single XOR.
+static ZOBRIST_KEYS: [[u64; 2]; BOARD_POSITIONS] = zobrist_keys();
+
+const fn zobrist_keys() -> [[u64; 2]; BOARD_POSITIONS] {
+    // splitmix64, which is simple enough to run in a const fn.
+    let mut state = 0x436F_6E6E_6563_7434_u64;
+    let mut keys = [[0; 2]; BOARD_POSITIONS];
+
+    let mut i = 0;
+    while i < BOARD_POSITIONS * 2 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        keys[i / 2][i % 2] = z ^ (z >> 31);
+        i += 1;
+    }
+
+    keys
+}
+
+fn zobrist_key(position: usize, player: Player) -> u64 {
+    ZOBRIST_KEYS[position][Player::as_u8(Some(player)) as usize]
+}
+
+/// The position on the other side of the board.
+fn mirror(position: usize) -> usize {
+    let row_start = position - position % WIDTH;
+    row_start + WIDTH - 1 - position % WIDTH
+}
+
 ///  0  1  2  3  4  5  6
 ///  7  8  9 10 11 12 13
 /// 14 15 16 17 18 19 20
@@ -19,16 +53,52 @@ const BOARD_POSITIONS: usize = WIDTH * HEIGTH;
 #[derive(Clone)]
 pub struct Connect4 {
     positions: [Position; BOARD_POSITIONS],
+    hash: u64,
+    /// The hash of the mirrored board, updated together with the hash.
+    mirrored_hash: u64,
+    /// The number of pieces of X and O, which tells whose turn it is.
+    pieces: [u8; 2],
+    /// Whether the positions were changed through [`IndexMut`], which can't update the hashes
+    /// and the piece counts.
+    stale: bool,
 }
 
 impl Connect4 {
     pub fn new() -> Self {
         Self {
             positions: [None; BOARD_POSITIONS],
+            hash: 0,
+            mirrored_hash: 0,
+            pieces: [0; 2],
+            stale: false,
+        }
+    }
+
+    /// The board with the hashes and piece counts up to date.
+    fn synced(&self) -> Cow<'_, Self> {
+        if self.stale {
+            // Transforming builds a new board with `set_pos`.
+            Cow::Owned(self.transform(0))
+        } else {
+            Cow::Borrowed(self)
         }
     }
 
     pub fn set_pos(&mut self, position: usize, value: Position) {
+        if self.stale {
+            *self = self.transform(0);
+        }
+        // XOR the old player out and the new one in.
+        if let Some(player) = self.positions[position] {
+            self.hash ^= zobrist_key(position, player);
+            self.mirrored_hash ^= zobrist_key(mirror(position), player);
+            self.pieces[Player::as_u8(Some(player)) as usize] -= 1;
+        }
+        if let Some(player) = value {
+            self.hash ^= zobrist_key(position, player);
+            self.mirrored_hash ^= zobrist_key(mirror(position), player);
+            self.pieces[Player::as_u8(Some(player)) as usize] += 1;
+        }
         self.positions[position] = value;
     }
 
@@ -147,6 +217,7 @@ impl Index<usize> for Connect4 {
 
 impl IndexMut<usize> for Connect4 {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        self.stale = true;
         &mut self.positions[index]
     }
 }
@@ -156,10 +227,51 @@ impl Game for Connect4 {
 
     const REASONABLE_SEARCH_DEPTH: Option<usize> = Some(11);
 
+    const MOVE_COUNT: usize = WIDTH;
+
+    fn move_index(position: Self::Move) -> usize {
+        position
+    }
+
     fn empty() -> Self {
         Self::new()
     }
 
+    fn from_notation(notation: &str) -> Result<Self, ParseError> {
+        let positions = notation::parse(notation, WIDTH, HEIGTH)?;
+        let mut board = Self::new();
+        for (position, &value) in positions.iter().enumerate() {
+            // Only the bottom row has nothing below it.
+            if value.is_some() && positions.get(position + WIDTH) == Some(&None) {
+                return Err(ParseError::FloatingPiece);
+            }
+            board.set_pos(position, value);
+        }
+        Ok(board)
+    }
+
+    fn to_notation(&self) -> String {
+        notation::write(self.positions, WIDTH, self.side_to_move())
+    }
+
+    fn parse_move(text: &str) -> Option<Self::Move> {
+        // Numbered from 1 like the letters, so `4` is `d`.
+        if let Ok(column) = text.parse::<usize>() {
+            return column.checked_sub(1);
+        }
+        let mut chars = text.chars();
+        let (Some(column), None) = (chars.next(), chars.next()) else {
+            return None;
+        };
+        column
+            .is_ascii_alphabetic()
+            .then(|| column.to_ascii_lowercase() as usize - 'a' as usize)
+    }
+
+    fn format_move(position: Self::Move) -> String {
+        char::from(b'a' + position as u8).to_string()
+    }
+
     fn possible_moves(&self) -> impl Iterator<Item = Self::Move> {
         let board = self.clone();
         [3, 2, 4, 1, 5, 0, 6]
@@ -171,9 +283,36 @@ impl Game for Connect4 {
         Connect4::result(&self)
     }
 
-    fn make_move(&mut self, position: Self::Move, player: Player) {
+    fn side_to_move(&self) -> Player {
+        let pieces = self.synced().pieces;
+        if pieces[0] > pieces[1] {
+            Player::O
+        } else {
+            Player::X
+        }
+    }
+
+    fn valid_side_to_move(&self) -> Option<Player> {
+        let pieces = self.synced().pieces;
+        Player::to_move(usize::from(pieces[0]), usize::from(pieces[1]))
+    }
+
+    fn make_move(&mut self, position: Self::Move) {
+        debug_assert!(self[position].is_none(), "column {position} is full");
         let pos = self.drop_player(position);
-        self[pos] = Some(player);
+        self.set_pos(pos, Some(self.side_to_move()));
+    }
+
+    fn check_move(&self, position: Self::Move) -> Result<(), MoveError> {
+        if position >= WIDTH {
+            Err(MoveError::OutOfRange)
+        } else if self.result() != State::InProgress {
+            Err(MoveError::GameOver)
+        } else if self[position].is_some() {
+            Err(MoveError::Occupied)
+        } else {
+            Ok(())
+        }
     }
 
     fn undo_move(&mut self, position: Self::Move) {
@@ -181,7 +320,7 @@ impl Game for Connect4 {
             let pos = position + (i * WIDTH);
 
             if self[pos].is_some() {
-                self[pos] = None;
+                self.set_pos(pos, None);
                 return;
             }
         }
@@ -190,6 +329,48 @@ impl Game for Connect4 {
     fn rate(&self, player: Player) -> Score<IgnorePlayer> {
         Connect4::rate(&self, player)
     }
+
+    fn hash(&self) -> u64 {
+        self.synced().hash
+    }
+
+    // Mirrored from left to right.
+    const SYMMETRIES: usize = 2;
+
+    fn transform(&self, symmetry: usize) -> Self {
+        let mut board = Self::new();
+        for (position, &value) in self.positions.iter().enumerate() {
+            let position = if symmetry == 1 {
+                mirror(position)
+            } else {
+                position
+            };
+            board.set_pos(position, value);
+        }
+        board
+    }
+
+    fn transform_move(position: Self::Move, symmetry: usize) -> Self::Move {
+        if symmetry == 1 {
+            mirror(position)
+        } else {
+            position
+        }
+    }
+
+    fn untransform_move(position: Self::Move, symmetry: usize) -> Self::Move {
+        // Mirroring twice does nothing.
+        Self::transform_move(position, symmetry)
+    }
+
+    fn canonical_hash(&self) -> (u64, usize) {
+        let board = self.synced();
+        if board.mirrored_hash < board.hash {
+            (board.mirrored_hash, 1)
+        } else {
+            (board.hash, 0)
+        }
+    }
 }
 
 impl Display for Connect4 {
@@ -205,7 +386,8 @@ impl Display for Connect4 {
                         write!(f, "\x1B[34m  O\x1B[0m  ")?;
                     }
                     None => {
-                        write!(f, "\x1B[35m{index:3 }\x1B[0m  ")?;
+                        // The column, as the players type it.
+                        write!(f, "\x1B[35m{:>3}\x1B[0m  ", Self::format_move(j))?;
                     }
                 }
             }
@@ -217,12 +399,12 @@ impl Display for Connect4 {
 
 #[cfg(test)]
 mod tests {
-    use crate::{Player, Score, State, state::IgnorePlayer};
+    use crate::{Game, MoveError, ParseError, Player, Score, State, state::IgnorePlayer};
 
-    use super::Connect4;
+    use super::{Connect4, BOARD_POSITIONS};
 
     fn parse_board(board: &str) -> Connect4 {
-        let positions = board
+        let positions: [_; BOARD_POSITIONS] = board
             .chars()
             .filter(|char| !char.is_whitespace())
             .map(|char| match char {
@@ -238,7 +420,11 @@ mod tests {
                 board.chars().filter(|c| !c.is_whitespace()).count()
             ));
 
-        Connect4 { positions }
+        let mut board = Connect4::new();
+        for (index, position) in positions.into_iter().enumerate() {
+            board.set_pos(index, position);
+        }
+        board
     }
 
     fn test_result(board: &str, state: State) {
@@ -345,4 +531,197 @@ mod tests {
             Score::new(0),
         )
     }
+
+    #[test]
+    fn hash_independent_of_move_order() {
+        let mut a = Connect4::new();
+        a.make_move(3);
+        a.make_move(2);
+        a.make_move(4);
+
+        let mut b = Connect4::new();
+        b.make_move(4);
+        b.make_move(2);
+        b.make_move(3);
+
+        assert_eq!(a.hash(), b.hash());
+
+        let parsed = parse_board(
+            "
+        _______
+        _______
+        _______
+        __OXX__
+        ",
+        );
+        assert_eq!(a.hash(), parsed.hash());
+    }
+
+    #[test]
+    fn undo_restores_hash() {
+        let mut board = Connect4::new();
+        board.make_move(3);
+        let before = board.hash();
+
+        board.make_move(3);
+        assert_ne!(board.hash(), before);
+
+        board.undo_move(3);
+        assert_eq!(board.hash(), before);
+
+        board.undo_move(3);
+        assert_eq!(board.hash(), Connect4::new().hash());
+    }
+
+    #[test]
+    fn mirrored_boards_are_canonical() {
+        let mut board = Connect4::new();
+        let mut mirrored = Connect4::new();
+        for pos in [0, 2, 2] {
+            board.make_move(pos);
+            mirrored.make_move(Connect4::transform_move(pos, 1));
+        }
+
+        assert_eq!(board.transform(1).hash(), mirrored.hash());
+        assert_eq!(board.canonical_hash().0, mirrored.canonical_hash().0);
+        assert_ne!(board.canonical_hash().1, mirrored.canonical_hash().1);
+        assert_eq!(board.canonical().0.hash(), board.canonical_hash().0);
+
+        // The middle column is its own mirror image.
+        let mut board = Connect4::new();
+        board.make_move(3);
+        assert_eq!(board.canonical_hash(), (board.hash(), 0));
+    }
+
+    #[test]
+    fn illegal_moves() {
+        let mut board = parse_board(
+            "
+        X______
+        O______
+        X______
+        O______
+        ",
+        );
+        assert_eq!(board.check_move(0), Err(MoveError::Occupied));
+        assert_eq!(board.check_move(7), Err(MoveError::OutOfRange));
+        assert_eq!(board.check_move(1), Ok(()));
+
+        let before = board.hash();
+        assert_eq!(board.try_make_move(0), Err(MoveError::Occupied));
+        assert_eq!(board.hash(), before);
+
+        for pos in [1, 2, 1, 2, 1, 2, 1] {
+            board.make_move(pos);
+        }
+        assert_eq!(board.result(), State::Winner(Player::X));
+        assert_eq!(board.check_move(3), Err(MoveError::GameOver));
+    }
+
+    #[test]
+    fn side_to_move_follows_the_pieces() {
+        let mut board = Connect4::new();
+        assert_eq!(board.side_to_move(), Player::X);
+        board.make_move(3);
+        assert_eq!(board.side_to_move(), Player::O);
+        assert_eq!(board[24], Some(Player::X));
+        board.make_move(3);
+        assert_eq!(board[17], Some(Player::O));
+        board.undo_move(3);
+        assert_eq!(board.side_to_move(), Player::O);
+
+        let board = parse_board(
+            "
+        _______
+        _______
+        _X_____
+        XO_____
+        ",
+        );
+        assert_eq!(board.valid_side_to_move(), Some(Player::O));
+
+        let board = parse_board(
+            "
+        _______
+        _______
+        _______
+        OO_____
+        ",
+        );
+        assert_eq!(board.valid_side_to_move(), None);
+    }
+
+    #[test]
+    fn index_mut_keeps_the_hash() {
+        let mut played = Connect4::new();
+        played.make_move(3);
+        played.make_move(0);
+
+        let mut board = Connect4::new();
+        board[24] = Some(Player::X);
+        board[21] = Some(Player::O);
+        assert_eq!(board.hash(), played.hash());
+        assert_eq!(board.canonical_hash(), played.canonical_hash());
+        assert_eq!(board.side_to_move(), Player::X);
+
+        // Moves after writing through the index keep the hash up to date.
+        played.make_move(6);
+        board.make_move(6);
+        assert_eq!(board.hash(), played.hash());
+        assert_eq!(board.side_to_move(), Player::O);
+    }
+
+    #[test]
+    fn notation_round_trip() {
+        let mut board = Connect4::new();
+        assert_eq!(board.to_notation(), "7/7/7/7 X");
+
+        for pos in [3, 3, 2, 6] {
+            board.make_move(pos);
+        }
+        let notation = board.to_notation();
+        assert_eq!(notation, "7/7/3O3/2XX2O X");
+
+        let parsed = Connect4::from_notation(&notation).unwrap();
+        assert_eq!(parsed.hash(), board.hash());
+        assert_eq!(parsed.canonical_hash(), board.canonical_hash());
+        assert_eq!(parsed.side_to_move(), Player::X);
+        assert_eq!(parsed.to_notation(), notation);
+
+        assert_eq!(
+            Connect4::from_notation("7/7/3O3/2X4 X").err(),
+            Some(ParseError::FloatingPiece)
+        );
+        assert_eq!(
+            Connect4::from_notation("7/7/7/2XX3 O").err(),
+            Some(ParseError::ImpossiblePieceCount)
+        );
+    }
+
+    #[test]
+    fn move_notation() {
+        assert_eq!(Connect4::format_move(0), "a");
+        assert_eq!(Connect4::format_move(3), "d");
+        for column in 0..7 {
+            let text = Connect4::format_move(column);
+            assert_eq!(Connect4::parse_move(&text), Some(column), "{text}");
+            assert_eq!(
+                Connect4::parse_move(&(column + 1).to_string()),
+                Some(column)
+            );
+        }
+
+        assert_eq!(Connect4::parse_move("4"), Connect4::parse_move("d"));
+        assert_eq!(Connect4::parse_move("0"), None);
+        assert_eq!(Connect4::parse_move("D"), Some(3));
+        assert_eq!(Connect4::parse_move("de"), None);
+        assert_eq!(Connect4::parse_move("?"), None);
+
+        // Columns that don't exist are for `check_move` to reject.
+        let column = Connect4::parse_move("h").unwrap();
+        assert_eq!(
+            Connect4::empty().check_move(column),
+            Err(MoveError::OutOfRange)
+        );
+    }
 }
@@ -0,0 +1,228 @@
+//! An opening book: the best moves for the first few plies, searched deeply ahead of time
+//! and saved to a file, so that the start of the game doesn't need any searching at all.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{table_file::TableFormat, transposition, Game, GamePlayer, State};
+
+use super::Connect4;
+
+const FORMAT: TableFormat = TableFormat {
+    magic: b"C4BK",
+    version: 2,
+    name: "opening book",
+};
+
+/// Best moves by position. Like in the transposition table, a position and its mirror image share
+/// an entry, so the positions are identified by their canonical hash and the moves are stored
+/// for the canonical board.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpeningBook {
+    /// Sorted by key for binary search.
+    entries: Vec<(u64, u8)>,
+}
+
+impl OpeningBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lets `player` pick the move in every position that can come up in the first `plies` plies
+    /// of a game. The player should search a lot deeper than it could during a game,
+    /// that's the point of the book.
+    pub fn build(plies: usize, player: &mut impl GamePlayer<Connect4>) -> Self {
+        let mut moves = HashMap::new();
+        let mut board = Connect4::new();
+        Self::build_position(&mut board, plies, player, &mut moves);
+
+        let mut book = Self::new();
+        for (key, column) in moves {
+            book.entries.push((key, column));
+        }
+        book.entries.sort_unstable();
+        book
+    }
+
+    fn build_position(
+        board: &mut Connect4,
+        plies: usize,
+        player: &mut impl GamePlayer<Connect4>,
+        moves: &mut HashMap<u64, u8>,
+    ) {
+        let (key, symmetry) = Self::key(board);
+        if plies == 0 || board.result() != State::InProgress || moves.contains_key(&key) {
+            return;
+        }
+
+        let column = player.next_move(board);
+        assert!(board.is_legal(column), "player made an illegal move");
+        moves.insert(key, Connect4::transform_move(column, symmetry) as u8);
+
+        let columns = board.possible_moves().collect::<Vec<_>>();
+        for column in columns {
+            board.make_move(column);
+            Self::build_position(board, plies - 1, player, moves);
+            board.undo_move(column);
+        }
+    }
+
+    /// The key of the position and the symmetry that turns `board` into the canonical board.
+    fn key(board: &Connect4) -> (u64, usize) {
+        let (hash, symmetry) = board.canonical_hash();
+        (transposition::key(hash, board.side_to_move()), symmetry)
+    }
+
+    /// Adds or replaces the move for a position.
+    pub fn insert(&mut self, board: &Connect4, column: usize) {
+        let (key, symmetry) = Self::key(board);
+        let column = Connect4::transform_move(column, symmetry);
+        match self.entries.binary_search_by_key(&key, |&(key, _)| key) {
+            Ok(index) => self.entries[index].1 = column as u8,
+            Err(index) => self.entries.insert(index, (key, column as u8)),
+        }
+    }
+
+    /// The move for `board`, if the position is in the book.
+    pub fn get(&self, board: &Connect4) -> Option<usize> {
+        let (key, symmetry) = Self::key(board);
+        self.entries
+            .binary_search_by_key(&key, |&(key, _)| key)
+            .ok()
+            .map(|index| Connect4::untransform_move(usize::from(self.entries[index].1), symmetry))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The format is the same as for the database, with the columns as the values.
+    pub fn write_to(&self, writer: impl Write) -> io::Result<()> {
+        FORMAT.write(writer, self.entries.iter().copied())
+    }
+
+    /// Reads a book written by [`OpeningBook::write_to`].
+    pub fn read_from(reader: impl Read) -> io::Result<Self> {
+        let entries = FORMAT.read(reader, |column| {
+            (usize::from(column) < super::board::WIDTH).then_some(column)
+        })?;
+        Ok(Self { entries })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Plays the move from the book if there is one, otherwise lets the inner player decide.
+pub struct OpeningBookPlayer<P> {
+    book: OpeningBook,
+    inner: P,
+}
+
+impl<P: GamePlayer<Connect4>> OpeningBookPlayer<P> {
+    pub fn new(book: OpeningBook, inner: P) -> Self {
+        Self { book, inner }
+    }
+}
+
+impl<P: GamePlayer<Connect4>> GamePlayer<Connect4> for OpeningBookPlayer<P> {
+    fn next_move(&mut self, board: &Connect4) -> usize {
+        match self.book.get(board) {
+            // A book for a different board size could have any column in it.
+            Some(column) if board.is_legal(column) => {
+                // The inner player might still be pondering on the opponent's move.
+                self.inner.stop_pondering();
+                column
+            }
+            _ => self.inner.next_move(board),
+        }
+    }
+
+    fn ponder(&mut self, board: &Connect4) {
+        self.inner.ponder(board)
+    }
+
+    fn stop_pondering(&mut self) {
+        self.inner.stop_pondering()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{player::GreedyPlayer, Game, GamePlayer, PerfectPlayer};
+
+    use super::{Connect4, OpeningBook, OpeningBookPlayer};
+
+    #[test]
+    fn build_covers_first_plies() {
+        let mut player = PerfectPlayer::new(false).with_max_depth(Some(4));
+        let book = OpeningBook::build(2, &mut player);
+
+        // The empty board and the seven boards after the first move, three of which are the
+        // mirror images of others.
+        assert_eq!(book.len(), 5);
+        let mut board = Connect4::new();
+        assert!(book.get(&board).is_some());
+        board.make_move(3);
+        board.make_move(3);
+        assert_eq!(book.get(&board), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut book = OpeningBook::new();
+        let mut board = Connect4::new();
+        book.insert(&board, 3);
+        board.make_move(3);
+        book.insert(&board, 2);
+
+        let mut bytes = Vec::new();
+        book.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 4 + 1 + 4 + 2 * 9);
+        assert_eq!(OpeningBook::read_from(&bytes[..]).unwrap(), book);
+
+        assert!(OpeningBook::read_from(&b"nope"[..]).is_err());
+        assert!(OpeningBook::read_from(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn mirror_images_share_an_entry() {
+        let mut book = OpeningBook::new();
+        let mut board = Connect4::new();
+        board.make_move(0);
+        book.insert(&board, 1);
+
+        let mut mirrored = Connect4::new();
+        mirrored.make_move(6);
+        assert_eq!(book.len(), 1);
+        assert_eq!(book.get(&mirrored), Some(5));
+        assert_eq!(book.get(&board), Some(1));
+    }
+
+    #[test]
+    fn book_player_falls_back() {
+        let mut book = OpeningBook::new();
+        let mut board = Connect4::new();
+        // Not what the greedy player would do.
+        book.insert(&board, 6);
+
+        let mut player = OpeningBookPlayer::new(book, GreedyPlayer);
+        assert_eq!(player.next_move(&board), 6);
+
+        board.make_move(6);
+        assert_eq!(player.next_move(&board), GreedyPlayer.next_move(&board));
+    }
+}
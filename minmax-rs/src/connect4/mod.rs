@@ -1,6 +1,6 @@
 use self::board::Connect4;
 
-pub use player::HumanPlayer;
+pub use book::{OpeningBook, OpeningBookPlayer};
 
 pub mod board;
-pub mod player;
+mod book;
@@ -0,0 +1,345 @@
+//! Retrograde analysis: every position that can be reached from a starting position is enumerated,
+//! and their values are worked out backwards from the finished games. The result is a database
+//! with the exact value of every position, so playing perfectly is just a lookup.
+//!
+//! Only works for small games or late positions, all of the positions have to fit into memory.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    marker::PhantomData,
+    mem,
+    path::Path,
+};
+
+use crate::{
+    state::IgnorePlayer, table_file::TableFormat, transposition, Game, GamePlayer, PerfectPlayer,
+    Score, State,
+};
+
+const FORMAT: TableFormat = TableFormat {
+    magic: b"MMDB",
+    version: 1,
+    name: "database",
+};
+
+/// A position found during the enumeration, before its value is known.
+struct Node {
+    key: u64,
+    parents: Vec<u32>,
+    /// The number of children that are not known to be won for the player to move there.
+    /// When it reaches zero, every move loses.
+    unresolved_children: u32,
+    /// Won in `n` plies is `n`, lost in `n` plies is `-n`. `None` until it's known.
+    value: Option<i8>,
+}
+
+/// The values of all positions that can be reached from where the database was built, from the
+/// view of the player to move. Finished games aren't in there, their value is obvious.
+#[derive(Clone)]
+pub struct Database<G> {
+    /// Sorted by the key of the board's hash and the side to move, for binary search. Unlike in
+    /// the transposition table, mirror images have their own entries.
+    /// The values are encoded like [`Node::value`], with `0` for a draw.
+    entries: Vec<(u64, i8)>,
+    game: PhantomData<fn() -> G>,
+}
+
+impl<G: Game> Database<G> {
+    /// Enumerates every position that can be reached from `board` and computes their values.
+    pub fn build(board: &G) -> Self {
+        let mut nodes = Vec::new();
+        let mut indices = HashMap::new();
+        let mut won = VecDeque::new();
+        let mut board = board.clone();
+        Self::enumerate(&mut board, None, &mut nodes, &mut indices, &mut won);
+
+        // Going through the positions in the order of their distance to the end makes sure that
+        // wins are as quick and losses as slow as possible. A position is won as soon as one move
+        // leads to a lost position, and lost once all of its moves lead to won ones. The queue
+        // starts with the wins in one ply and only ever gets values one ply further away.
+        let mut queue = won;
+        while let Some(index) = queue.pop_front() {
+            let value = i32::from(nodes[index as usize].value.expect("queued without a value"));
+            for parent in mem::take(&mut nodes[index as usize].parents) {
+                let node = &mut nodes[parent as usize];
+                if node.value.is_some() {
+                    continue;
+                }
+                if value < 0 {
+                    node.value = Some(Self::encode(1 - value));
+                    queue.push_back(parent);
+                } else {
+                    node.unresolved_children -= 1;
+                    if node.unresolved_children == 0 {
+                        node.value = Some(Self::encode(-1 - value));
+                        queue.push_back(parent);
+                    }
+                }
+            }
+        }
+
+        // Whatever couldn't be decided by now can't be won by either player.
+        let mut entries = nodes
+            .into_iter()
+            .map(|node| (node.key, node.value.unwrap_or(0)))
+            .collect::<Vec<_>>();
+        entries.sort_unstable();
+
+        Self {
+            entries,
+            game: PhantomData,
+        }
+    }
+
+    /// Adds the position and everything after it to `nodes`. Finished games don't get a node,
+    /// the positions where a move wins right away are queued in `won` instead.
+    fn enumerate(
+        board: &mut G,
+        parent: Option<u32>,
+        nodes: &mut Vec<Node>,
+        indices: &mut HashMap<u64, u32>,
+        won: &mut VecDeque<u32>,
+    ) {
+        let key = transposition::key(board.hash(), board.side_to_move());
+        if let Some(&index) = indices.get(&key) {
+            nodes[index as usize].parents.extend(parent);
+            return;
+        }
+
+        let index = u32::try_from(nodes.len()).expect("too many positions for a database");
+        indices.insert(key, index);
+        nodes.push(Node {
+            key,
+            parents: parent.into_iter().collect(),
+            unresolved_children: 0,
+            value: None,
+        });
+
+        let moves = board.possible_moves().collect::<Vec<_>>();
+        let mut wins_now = false;
+        let mut children = 0;
+        for pos in moves {
+            board.make_move(pos);
+            match board.result() {
+                State::Winner(_) => wins_now = true,
+                // Drawn games are never won for the opponent, so they stay unresolved forever
+                // and the position can't be lost anymore.
+                State::Draw => children += 1,
+                State::InProgress => {
+                    children += 1;
+                    Self::enumerate(board, Some(index), nodes, indices, won);
+                }
+            }
+            board.undo_move(pos);
+        }
+
+        let node = &mut nodes[index as usize];
+        node.unresolved_children = children;
+        if wins_now {
+            node.value = Some(1);
+            won.push_back(index);
+        }
+    }
+
+    fn encode(value: i32) -> i8 {
+        i8::try_from(value).expect("the game is too long for a database")
+    }
+
+    /// The exact value of the position for the side to move, if the database has it.
+    pub fn get(&self, board: &G) -> Option<Score<IgnorePlayer>> {
+        let key = transposition::key(board.hash(), board.side_to_move());
+        let index = self
+            .entries
+            .binary_search_by_key(&key, |&(key, _)| key)
+            .ok()?;
+        Some(match self.entries[index].1 {
+            0 => Score::TIE,
+            plies @ 1.. => Score::won_in(plies as usize),
+            plies => Score::lost_in(plies.unsigned_abs() as usize),
+        })
+    }
+
+    /// The value of the position after the side to move makes the move, from their view.
+    fn value_after(&self, board: &mut G, pos: G::Move) -> Option<Score<IgnorePlayer>> {
+        board.make_move(pos);
+        let value = match board.result() {
+            State::Winner(_) => Some(Score::won_in(1)),
+            State::Draw => Some(Score::TIE),
+            State::InProgress => self.get(board).map(|score| {
+                // One more ply from here than from the next position.
+                match (score.plies_to_win(), score.plies_to_loss()) {
+                    (Some(plies), _) => Score::lost_in(plies + 1),
+                    (_, Some(plies)) => Score::won_in(plies + 1),
+                    _ => Score::TIE,
+                }
+            }),
+        };
+        board.undo_move(pos);
+        value
+    }
+
+    /// The best move for the side to move, if the database knows the position after every move.
+    pub fn best_move(&self, board: &G) -> Option<G::Move> {
+        let mut board = board.clone();
+        let moves = board.possible_moves().collect::<Vec<_>>();
+
+        let mut best = None;
+        for pos in moves {
+            let value = self.value_after(&mut board, pos)?;
+            match best {
+                Some((_, best_value)) if value <= best_value => {}
+                _ => best = Some((pos, value)),
+            }
+        }
+        best.map(|(pos, _)| pos)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The format is the same as for the opening book, with the values as two's complement bytes.
+    pub fn write_to(&self, writer: impl Write) -> io::Result<()> {
+        let entries = self.entries.iter().map(|&(key, value)| (key, value as u8));
+        FORMAT.write(writer, entries)
+    }
+
+    /// Reads a database written by [`Database::write_to`]. The game isn't in the file,
+    /// a database of another game just gives wrong answers.
+    pub fn read_from(reader: impl Read) -> io::Result<Self> {
+        Ok(Self {
+            entries: FORMAT.read(reader, |value| Some(value as i8))?,
+            game: PhantomData,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Plays the best move from the database. Positions that aren't in there, because the game
+/// started before the position the database was built from, are left to another player.
+pub struct DatabasePlayer<G: Game, P = PerfectPlayer<G>> {
+    database: Database<G>,
+    fallback: P,
+}
+
+impl<G: Game> DatabasePlayer<G> {
+    pub fn new(database: Database<G>) -> Self {
+        Self {
+            database,
+            fallback: PerfectPlayer::new(false),
+        }
+    }
+}
+
+impl<G: Game, P: GamePlayer<G>> DatabasePlayer<G, P> {
+    /// The player for the positions that aren't in the database. [`PerfectPlayer`] by default.
+    pub fn with_fallback<P2: GamePlayer<G>>(self, fallback: P2) -> DatabasePlayer<G, P2> {
+        DatabasePlayer {
+            database: self.database,
+            fallback,
+        }
+    }
+}
+
+impl<G: Game, P: GamePlayer<G>> GamePlayer<G> for DatabasePlayer<G, P> {
+    fn next_move(&mut self, board: &G) -> G::Move {
+        match self.database.best_move(board) {
+            Some(pos) => {
+                self.fallback.stop_pondering();
+                pos
+            }
+            None => self.fallback.next_move(board),
+        }
+    }
+
+    fn ponder(&mut self, board: &G) {
+        self.fallback.ponder(board)
+    }
+
+    fn stop_pondering(&mut self) {
+        self.fallback.stop_pondering()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        connect4::board::Connect4, player::RandomPlayer, tic_tac_toe::TicTacToe, Game, GamePlayer,
+        PerfectPlayer, Player, Score, State,
+    };
+
+    use super::{Database, DatabasePlayer};
+
+    #[test]
+    fn tic_tac_toe_is_a_draw() {
+        let database = Database::build(&TicTacToe::empty());
+        // All positions that can come up in a game, without the finished ones.
+        assert_eq!(database.len(), 4520);
+        assert_eq!(database.get(&TicTacToe::empty()), Some(Score::TIE));
+    }
+
+    #[test]
+    fn agrees_with_solver() {
+        let mut board = Connect4::empty();
+        for pos in [3, 3, 3, 3, 2, 4, 4, 2, 2, 2, 4, 4, 1, 5, 5, 1] {
+            board.make_move(pos);
+        }
+        let database = Database::build(&board);
+
+        // Follow some random games and compare every position along the way.
+        let mut solver = PerfectPlayer::new(false);
+        for _ in 0..5 {
+            let mut board = board.clone();
+            while board.result() == State::InProgress {
+                assert_eq!(database.get(&board), solver.solve(&board), "{board}");
+                let pos = RandomPlayer.next_move(&board);
+                board.make_move(pos);
+            }
+        }
+    }
+
+    #[test]
+    fn never_loses() {
+        let database = Database::build(&TicTacToe::empty());
+        for _ in 0..20 {
+            let mut board = TicTacToe::empty();
+            let result = board.play(
+                &mut DatabasePlayer::new(database.clone()),
+                &mut RandomPlayer,
+            );
+            assert_ne!(result, Some(Player::O));
+
+            let mut board = TicTacToe::empty();
+            let result = board.play(
+                &mut RandomPlayer,
+                &mut DatabasePlayer::new(database.clone()),
+            );
+            assert_ne!(result, Some(Player::X));
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let database = Database::build(&TicTacToe::empty());
+
+        let mut bytes = Vec::new();
+        database.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 4 + 1 + 4 + database.len() * 9);
+        let read = Database::<TicTacToe>::read_from(&bytes[..]).unwrap();
+        assert_eq!(read.entries, database.entries);
+        assert!(Database::<TicTacToe>::read_from(&bytes[..10]).is_err());
+    }
+}
@@ -6,42 +6,133 @@
 )]
 #![allow(incomplete_features)]
 
+mod background;
 pub mod connect4;
+mod database;
+mod mcts;
 mod minmax;
+mod notation;
 pub mod player;
+mod ponder;
+mod proof_number;
 mod state;
+mod stats;
+mod table_file;
 pub mod tic_tac_toe;
+mod transposition;
 
-use std::fmt::Display;
+use std::{
+    error::Error,
+    fmt::{Debug, Display},
+};
 
 use state::IgnorePlayer;
 
-pub use self::minmax::PerfectPlayer;
+pub use self::background::{BackgroundSearch, StopHandle};
+pub use self::database::{Database, DatabasePlayer};
+pub use self::mcts::MctsPlayer;
+pub use self::minmax::{PerfectPlayer, SearchAlgorithm};
+pub use self::notation::ParseError;
+pub use self::ponder::PonderingPlayer;
+pub use self::proof_number::{ProofNumberSearch, ProofResult, ProofTree};
 pub use self::state::{Player, Score, State};
+pub use self::stats::SearchStats;
 
-pub trait GamePlayer<G: ?Sized + Game> {
-    fn next_move(&mut self, board: &mut G, this_player: Player);
+pub trait GamePlayer<G: Game> {
+    /// Picks the move for the [side to move](Game::side_to_move). The player only gets to look
+    /// at the board, [`Game::play`] checks that the move is legal and makes it.
+    fn next_move(&mut self, board: &G) -> G::Move;
+
+    /// Called after our move while the opponent is thinking about theirs.
+    /// Players that want to use that time start working in the background here.
+    fn ponder(&mut self, _board: &G) {}
+
+    /// Called when the game is over and the opponent's move will never come.
+    /// [`GamePlayer::next_move`] has to stop pondering by itself.
+    fn stop_pondering(&mut self) {}
 }
 
 impl<G: Game, P: GamePlayer<G> + ?Sized> GamePlayer<G> for &mut P {
-    fn next_move(&mut self, board: &mut G, this_player: Player) {
-        P::next_move(self, board, this_player)
+    fn next_move(&mut self, board: &G) -> G::Move {
+        P::next_move(self, board)
+    }
+
+    fn ponder(&mut self, board: &G) {
+        P::ponder(self, board)
+    }
+
+    fn stop_pondering(&mut self) {
+        P::stop_pondering(self)
     }
 }
 
 impl<G: Game, P: GamePlayer<G> + ?Sized> GamePlayer<G> for Box<P> {
-    fn next_move(&mut self, board: &mut G, this_player: Player) {
-        P::next_move(self, board, this_player)
+    fn next_move(&mut self, board: &G) -> G::Move {
+        P::next_move(self, board)
+    }
+
+    fn ponder(&mut self, board: &G) {
+        P::ponder(self, board)
     }
+
+    fn stop_pondering(&mut self) {
+        P::stop_pondering(self)
+    }
+}
+
+/// Why a move can't be made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    /// There is no such position on the board.
+    OutOfRange,
+    /// The position is taken already.
+    Occupied,
+    /// Nobody can move anymore once the game is over.
+    GameOver,
 }
 
-pub trait Game: Display {
-    type Move: Copy;
+impl Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::OutOfRange => "the move is not on the board",
+            Self::Occupied => "the position is occupied already",
+            Self::GameOver => "the game is over already",
+        })
+    }
+}
+
+impl Error for MoveError {}
+
+pub trait Game: Display + Clone + Send + Sync {
+    type Move: Copy + PartialEq + Debug + Send + Sync;
 
     const REASONABLE_SEARCH_DEPTH: Option<usize>;
 
+    /// The number of different moves in the whole game, not just in one position. The search keeps
+    /// a table about every move.
+    const MOVE_COUNT: usize;
+
+    /// A number for the move below [`Game::MOVE_COUNT`], to look it up in those tables.
+    fn move_index(position: Self::Move) -> usize;
+
     fn empty() -> Self;
 
+    /// Reads a position written by [`Game::to_notation`].
+    fn from_notation(notation: &str) -> Result<Self, ParseError>;
+
+    /// The position as text for bug reports, tests and the command line: the rows from top
+    /// to bottom separated by `/`, with `X` and `O` for the pieces and a number for a run of
+    /// empty positions, then the side to move. The empty tic tac toe board is `3/3/3 X`.
+    fn to_notation(&self) -> String;
+
+    /// Reads a move written by [`Game::format_move`], or `None` if the text isn't a move.
+    /// It doesn't have to be legal, that's up to [`Game::check_move`]. Games that also take bare
+    /// numbers count them from 1, like the numbers on their boards.
+    fn parse_move(text: &str) -> Option<Self::Move>;
+
+    /// The move as text for people, like `d` for a connect4 column or `b2` in tic tac toe.
+    fn format_move(position: Self::Move) -> String;
+
     /// Returns an iterator of all possible moves. Should be ordered best to worst.
     fn possible_moves(&self) -> impl Iterator<Item = Self::Move>;
 
@@ -50,34 +141,126 @@ pub trait Game: Display {
     /// Only called if [`GameBoard::REASONABLE_SEARCH_DEPTH`] is `Some`.
     fn rate(&self, player: Player) -> Score<IgnorePlayer>;
 
-    fn make_move(&mut self, position: Self::Move, player: Player);
+    /// Whose turn it is. X always starts, so it follows from the number of pieces on the board.
+    fn side_to_move(&self) -> Player;
+
+    /// Whose turn it is, or `None` if the number of pieces can't come up in a game.
+    /// [`Game::side_to_move`] just assumes the board is fine, boards from outside of the program
+    /// should be checked with this. Games whose boards can be put together by hand override it.
+    fn valid_side_to_move(&self) -> Option<Player> {
+        Some(self.side_to_move())
+    }
+
+    /// Makes the move for the [side to move](Game::side_to_move).
+    /// Only for legal moves, anything else can leave the board in a broken state.
+    /// Moves from outside of the program should go through [`Game::try_make_move`].
+    fn make_move(&mut self, position: Self::Move);
+
+    /// Checks whether the move can be made, and if not, why.
+    fn check_move(&self, position: Self::Move) -> Result<(), MoveError>;
+
+    fn is_legal(&self, position: Self::Move) -> bool {
+        self.check_move(position).is_ok()
+    }
+
+    /// Makes the move if it's legal.
+    fn try_make_move(&mut self, position: Self::Move) -> Result<(), MoveError> {
+        self.check_move(position)?;
+        self.make_move(position);
+        Ok(())
+    }
 
     fn undo_move(&mut self, position: Self::Move);
 
+    /// A hash of the board, used as the key for the transposition table.
+    /// It's requested for every searched node, so it should be updated incrementally
+    /// in [`Game::make_move`] and [`Game::undo_move`] instead of being computed here.
+    fn hash(&self) -> u64;
+
+    /// The number of symmetries of the board, like mirroring it, including the one that leaves
+    /// it as it is. Symmetric boards have the same value, so they only need to be searched once.
+    const SYMMETRIES: usize = 1;
+
+    /// Applies the symmetry `symmetry`, which is below [`Game::SYMMETRIES`].
+    /// Symmetry 0 leaves the board as it is.
+    fn transform(&self, _symmetry: usize) -> Self {
+        self.clone()
+    }
+
+    /// The move that does on the transformed board what `position` does on this one.
+    fn transform_move(position: Self::Move, _symmetry: usize) -> Self::Move {
+        position
+    }
+
+    /// The inverse of [`Game::transform_move`].
+    fn untransform_move(position: Self::Move, _symmetry: usize) -> Self::Move {
+        position
+    }
+
+    /// The symmetric board with the smallest hash, and the symmetry that turns this board into it.
+    /// All boards that are symmetric to each other have the same canonical board.
+    fn canonical(&self) -> (Self, usize) {
+        (0..Self::SYMMETRIES)
+            .map(|symmetry| (self.transform(symmetry), symmetry))
+            .min_by_key(|(board, _)| board.hash())
+            .expect("there is always the symmetry that does nothing")
+    }
+
+    /// The hash of the [canonical](Game::canonical) board and the symmetry that gets there.
+    /// It's requested for every searched node like [`Game::hash`], so games with symmetries
+    /// should compute it without transforming the whole board.
+    fn canonical_hash(&self) -> (u64, usize) {
+        if Self::SYMMETRIES == 1 {
+            return (self.hash(), 0);
+        }
+        let (board, symmetry) = self.canonical();
+        (board.hash(), symmetry)
+    }
+
+    /// Lets the players make their moves until the game is over and returns the winner.
+    /// A player that tries to make an illegal move loses the game.
     fn play<A: GamePlayer<Self>, B: GamePlayer<Self>>(
         &mut self,
         x: &mut A,
         o: &mut B,
     ) -> Option<Player> {
-        let mut current_player = Player::X;
+        // A position that is already over has no moves to ask the players for.
+        match self.result() {
+            State::Winner(player) => return Some(player),
+            State::Draw => return None,
+            State::InProgress => {}
+        }
 
-        loop {
-            if current_player == Player::X {
-                x.next_move(self, current_player);
+        let result = loop {
+            let current_player = self.side_to_move();
+            let position = if current_player == Player::X {
+                x.next_move(self)
             } else {
-                o.next_move(self, current_player);
+                o.next_move(self)
+            };
+
+            if self.try_make_move(position).is_err() {
+                break Some(current_player.opponent());
             }
 
             match self.result() {
-                State::Winner(player) => return Some(player),
+                State::Winner(player) => break Some(player),
                 State::Draw => {
-                    return None;
+                    break None;
                 }
                 State::InProgress => {}
             }
 
-            current_player = current_player.opponent();
-        }
+            if current_player == Player::X {
+                x.ponder(self);
+            } else {
+                o.ponder(self);
+            }
+        };
+
+        x.stop_pondering();
+        o.stop_pondering();
+        result
     }
 }
 
@@ -102,3 +285,44 @@ fn assert_win_ratio<G: Game, X: GamePlayer<G>, O: GamePlayer<G>>(
     println!("{ratio} >= {x_win_ratio}");
     assert!(ratio >= x_win_ratio);
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::player::GreedyPlayer;
+    use crate::tic_tac_toe::TicTacToe;
+    use crate::{Game, GamePlayer, Player};
+
+    /// Always wants the same position, which is only legal once.
+    struct StubbornPlayer;
+
+    impl GamePlayer<TicTacToe> for StubbornPlayer {
+        fn next_move(&mut self, _board: &TicTacToe) -> usize {
+            4
+        }
+    }
+
+    #[test]
+    fn illegal_move_loses() {
+        let mut board = TicTacToe::empty();
+        assert_eq!(
+            board.play(&mut StubbornPlayer, &mut GreedyPlayer),
+            Some(Player::O)
+        );
+        // The illegal move wasn't made.
+        assert_eq!(board.get(4), Some(Player::X));
+        assert_eq!(board.possible_moves().count(), 7);
+    }
+
+    #[test]
+    fn finished_position_is_not_played() {
+        // The stubborn player would lose for trying to move on a full board.
+        let mut board = TicTacToe::from_notation("XOX/OOX/XXO O").unwrap();
+        assert_eq!(board.play(&mut StubbornPlayer, &mut StubbornPlayer), None);
+
+        let mut board = TicTacToe::from_notation("XXX/OO1/3 O").unwrap();
+        assert_eq!(
+            board.play(&mut StubbornPlayer, &mut StubbornPlayer),
+            Some(Player::X)
+        );
+    }
+}
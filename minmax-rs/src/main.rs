@@ -1,13 +1,17 @@
-#![feature(let_chains)]
-
-use std::{fmt::Display, str::FromStr, time::SystemTime};
+use std::{
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    time::SystemTime,
+};
 
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use minmax::{
-    connect4::{self, board::Connect4},
-    player::{GreedyPlayer, RandomPlayer},
-    tic_tac_toe::{self, TicTacToe},
-    Game, GamePlayer, PerfectPlayer, Player,
+    connect4::{board::Connect4, OpeningBook, OpeningBookPlayer},
+    player::{GreedyPlayer, HumanPlayer, RandomPlayer},
+    tic_tac_toe::TicTacToe,
+    Database, DatabasePlayer, Game, GamePlayer, MctsPlayer, PerfectPlayer, Player, PonderingPlayer,
+    ProofNumberSearch, ProofResult, SearchAlgorithm, State,
 };
 
 #[derive(Debug, Clone)]
@@ -15,7 +19,12 @@ enum PlayerConfig {
     Human,
     Greedy,
     Random,
-    Perfect { depth: Option<usize> },
+    Perfect {
+        depth: Option<usize>,
+        skill: Option<u8>,
+        nodes: Option<u64>,
+    },
+    Mcts,
 }
 
 impl FromStr for PlayerConfig {
@@ -28,22 +37,54 @@ impl FromStr for PlayerConfig {
             .ok_or_else(|| "No player name provided".to_owned())?
         {
             "human" | "h" => Self::Human,
-            "perfect" | "p" | "ai" | "minmax" => Self::Perfect { depth: None },
+            "perfect" | "p" | "ai" | "minmax" => Self::Perfect {
+                depth: None,
+                skill: None,
+                nodes: None,
+            },
             "greedy" | "g" => Self::Greedy,
             "random" | "r" => Self::Random,
+            "mcts" | "m" => Self::Mcts,
             string => {
                 return Err(format!(
-                    "Invalid player: {string}. Available players: human,perfect"
+                    "Invalid player: {string}. Available players: human,perfect,greedy,random,mcts"
                 ))
             }
         };
 
-        if let Some(depth) = parts.next()
-            && let Self::Perfect { depth: player_depth } = &mut player
+        // Only the perfect player has options, like `perfect:8` or `perfect:skill=3`.
+        if let Self::Perfect {
+            depth,
+            skill,
+            nodes,
+        } = &mut player
         {
-            match depth.parse() {
-                Ok(depth) => *player_depth = Some(depth),
-                Err(err) => return Err(format!("Invalid depth: {depth}. {err}")),
+            for option in parts {
+                // A plain number is the depth, that's how it used to be.
+                let (name, value) = option.split_once('=').unwrap_or(("depth", option));
+                match name {
+                    "depth" => match value.parse() {
+                        Ok(value) => *depth = Some(value),
+                        Err(err) => return Err(format!("Invalid depth: {value}. {err}")),
+                    },
+                    "skill" => match value.parse() {
+                        Ok(level @ 1..=10) => *skill = Some(level),
+                        _ => {
+                            return Err(format!(
+                                "Invalid skill level: {value}. It must be between 1 and 10"
+                            ))
+                        }
+                    },
+                    "nodes" => match value.parse() {
+                        Ok(value) => *nodes = Some(value),
+                        Err(err) => return Err(format!("Invalid node limit: {value}. {err}")),
+                    },
+                    _ => {
+                        return Err(format!(
+                            "Invalid option: {name}. Available options: depth,skill,nodes"
+                        ))
+                    }
+                }
             }
         }
 
@@ -57,62 +98,409 @@ enum GameType {
     Connect4,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Algorithm {
+    AlphaBeta,
+    Pvs,
+}
+
+impl From<Algorithm> for SearchAlgorithm {
+    fn from(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::AlphaBeta => SearchAlgorithm::AlphaBeta,
+            Algorithm::Pvs => SearchAlgorithm::Pvs,
+        }
+    }
+}
+
 #[derive(Debug, Parser)]
-#[command(author, version, about)]
+#[command(author, version, about, subcommand_negates_reqs = true)]
 struct Args {
-    #[arg(short, long)]
-    game: GameType,
-    #[arg(short)]
-    x: PlayerConfig,
-    #[arg(short)]
-    o: PlayerConfig,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(short, long, required = true)]
+    game: Option<GameType>,
+    #[arg(short, required = true)]
+    x: Option<PlayerConfig>,
+    #[arg(short, required = true)]
+    o: Option<PlayerConfig>,
     #[arg(long)]
     no_print_time: bool,
+    /// Let the perfect players think while the opponent is thinking.
+    #[arg(long)]
+    ponder: bool,
+    /// Let the perfect players pick randomly between the moves that are at most this much worse
+    /// than the best one, so that the games aren't always the same.
+    #[arg(long)]
+    variety: Option<u32>,
+    /// The seed for the random choices of the perfect players.
+    #[arg(long)]
+    seed: Option<u64>,
+    /// The search algorithm of the perfect players.
+    #[arg(long, value_enum, default_value_t = Algorithm::AlphaBeta)]
+    algorithm: Algorithm,
+    /// An opening book for the perfect players, made with the `book` command. Only for connect4.
+    #[arg(long)]
+    book: Option<PathBuf>,
+    /// A database for the perfect players, made with the `database` command.
+    /// They play the positions in it perfectly without searching.
+    #[arg(long)]
+    database: Option<PathBuf>,
+    /// The position to start the game from, like `3/1X1/3 O` for tic tac toe.
+    #[arg(long)]
+    position: Option<String>,
+}
+
+/// The position that a command looks at.
+#[derive(Debug, clap::Args)]
+struct StartPosition {
+    /// The position to start from, like `7/7/7/3X3 O` for connect4. The empty board by default.
+    #[arg(long)]
+    position: Option<String>,
+    /// The moves that are played from there, like `d` or `4` for a connect4 column and `b2` or `5`
+    /// in tic tac toe. Numbers count from 1. Leave them out for the position itself.
+    moves: Vec<String>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Builds an opening book for connect4 by searching every position of the first plies deeply.
+    Book {
+        /// Where to write the book.
+        output: PathBuf,
+        /// The number of plies from the start of the game that the book covers.
+        #[arg(long, default_value_t = 4)]
+        plies: usize,
+        /// How deep to search every position.
+        #[arg(long, default_value_t = 16)]
+        depth: usize,
+    },
+    /// Solves a connect4 position completely, without any depth limit.
+    Solve {
+        #[command(flatten)]
+        start: StartPosition,
+    },
+    /// Builds a database with the value of every position that can come up after a position.
+    /// All of them have to fit into memory, so it only works for tic tac toe or late positions.
+    Database {
+        #[arg(short, long)]
+        game: GameType,
+        /// Where to write the database.
+        output: PathBuf,
+        #[command(flatten)]
+        start: StartPosition,
+    },
+    /// Uses proof-number search to find out whether a player can force a win.
+    Prove {
+        #[arg(short, long)]
+        game: GameType,
+        /// The player that should win. The player to move by default.
+        #[arg(long, value_enum)]
+        attacker: Option<Side>,
+        /// Give up after this many positions.
+        #[arg(long)]
+        nodes: Option<usize>,
+        #[command(flatten)]
+        start: StartPosition,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Side {
+    X,
+    O,
+}
+
+impl From<Side> for Player {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::X => Player::X,
+            Side::O => Player::O,
+        }
+    }
 }
 
 fn main() {
     let args = Args::parse();
 
-    match args.game {
+    match &args.command {
+        Some(Command::Book {
+            output,
+            plies,
+            depth,
+        }) => {
+            build_book(output, *plies, *depth);
+            return;
+        }
+        Some(Command::Solve { start }) => {
+            solve(start);
+            return;
+        }
+        Some(Command::Database {
+            game,
+            output,
+            start,
+        }) => {
+            match game {
+                GameType::Connect4 => build_database::<Connect4>(output, start),
+                GameType::TicTacToe => build_database::<TicTacToe>(output, start),
+            }
+            return;
+        }
+        Some(Command::Prove {
+            game,
+            attacker,
+            nodes,
+            start,
+        }) => {
+            let attacker = attacker.map(Player::from);
+            match game {
+                GameType::Connect4 => prove::<Connect4>(attacker, *nodes, start),
+                GameType::TicTacToe => prove::<TicTacToe>(attacker, *nodes, start),
+            }
+            return;
+        }
+        None => {}
+    }
+
+    let book = args.book.as_ref().map(|path| {
+        if args.game != Some(GameType::Connect4) {
+            eprintln!("Opening books only exist for connect4");
+            process::exit(1);
+        }
+        OpeningBook::load(path).unwrap_or_else(|err| {
+            eprintln!("Could not load the opening book {}: {err}", path.display());
+            process::exit(1);
+        })
+    });
+
+    match args.game.expect("the game is required") {
         GameType::Connect4 => {
+            let database = load_database(&args);
             let get_player = |player| -> Box<dyn GamePlayer<Connect4>> {
                 match player {
-                    PlayerConfig::Human => Box::new(connect4::HumanPlayer),
+                    PlayerConfig::Human => Box::new(HumanPlayer),
                     PlayerConfig::Greedy => Box::new(GreedyPlayer),
                     PlayerConfig::Random => Box::new(RandomPlayer),
-                    PlayerConfig::Perfect { depth } => Box::new(
-                        PerfectPlayer::new(!args.no_print_time)
-                            .with_max_depth(depth.or(Connect4::REASONABLE_SEARCH_DEPTH)),
-                    ),
+                    PlayerConfig::Perfect {
+                        depth,
+                        skill,
+                        nodes,
+                    } => {
+                        let player = perfect_player(&args, database.as_ref(), depth, skill, nodes);
+                        match &book {
+                            Some(book) => Box::new(OpeningBookPlayer::new(book.clone(), player)),
+                            None => player,
+                        }
+                    }
+                    PlayerConfig::Mcts => Box::new(MctsPlayer::new()),
                 }
             };
 
-            let player_a = get_player(args.x);
-            let player_b = get_player(args.o);
+            let player_a = get_player(args.x.clone().expect("x is required"));
+            let player_b = get_player(args.o.clone().expect("o is required"));
 
-            play_with_players(player_a, player_b);
+            play_with_players(parse_position(args.position.as_deref()), player_a, player_b);
         }
         GameType::TicTacToe => {
+            let database = load_database(&args);
             let get_player = |player| -> Box<dyn GamePlayer<TicTacToe>> {
                 match player {
-                    PlayerConfig::Human => Box::new(tic_tac_toe::HumanPlayer),
+                    PlayerConfig::Human => Box::new(HumanPlayer),
                     PlayerConfig::Greedy => Box::new(GreedyPlayer),
                     PlayerConfig::Random => Box::new(RandomPlayer),
-                    PlayerConfig::Perfect { depth } => Box::new(
-                        PerfectPlayer::new(!args.no_print_time)
-                            .with_max_depth(depth.or(TicTacToe::REASONABLE_SEARCH_DEPTH)),
-                    ),
+                    PlayerConfig::Perfect {
+                        depth,
+                        skill,
+                        nodes,
+                    } => perfect_player(&args, database.as_ref(), depth, skill, nodes),
+                    PlayerConfig::Mcts => Box::new(MctsPlayer::new()),
                 }
             };
 
-            let player_a = get_player(args.x);
-            let player_b = get_player(args.o);
+            let player_a = get_player(args.x.clone().expect("x is required"));
+            let player_b = get_player(args.o.clone().expect("o is required"));
 
-            play_with_players(player_a, player_b);
+            play_with_players(parse_position(args.position.as_deref()), player_a, player_b);
         }
     }
 }
 
+fn load_database<G: Game>(args: &Args) -> Option<Database<G>> {
+    args.database.as_ref().map(|path| {
+        Database::load(path).unwrap_or_else(|err| {
+            eprintln!("Could not load the database {}: {err}", path.display());
+            process::exit(1);
+        })
+    })
+}
+
+fn perfect_player<G: Game + 'static>(
+    args: &Args,
+    database: Option<&Database<G>>,
+    depth: Option<usize>,
+    skill: Option<u8>,
+    nodes: Option<u64>,
+) -> Box<dyn GamePlayer<G>> {
+    let mut player = PerfectPlayer::new(!args.no_print_time).with_algorithm(args.algorithm.into());
+    if let Some(level) = skill {
+        player = player.with_skill(level);
+    }
+    if let Some(depth) = depth {
+        player = player.with_max_depth(Some(depth));
+    }
+    if let Some(nodes) = nodes {
+        player = player.with_node_limit(nodes);
+    }
+    if let Some(margin) = args.variety {
+        player = player.with_variety(margin);
+    }
+    if let Some(seed) = args.seed {
+        player = player.with_seed(seed);
+    }
+
+    let player: Box<dyn GamePlayer<G>> = if args.ponder {
+        Box::new(PonderingPlayer::new(player))
+    } else {
+        Box::new(player)
+    };
+
+    match database {
+        Some(database) => Box::new(DatabasePlayer::new(database.clone()).with_fallback(player)),
+        None => player,
+    }
+}
+
+fn build_book(output: &Path, plies: usize, depth: usize) {
+    let start = SystemTime::now();
+
+    let mut player = PerfectPlayer::new(false).with_max_depth(Some(depth));
+    let book = OpeningBook::build(plies, &mut player);
+    if let Err(err) = book.save(output) {
+        eprintln!(
+            "Could not write the opening book {}: {err}",
+            output.display()
+        );
+        process::exit(1);
+    }
+
+    println!(
+        "Wrote {} positions to {} in {}s",
+        book.len(),
+        output.display(),
+        start.elapsed().unwrap().as_secs()
+    );
+}
+
+fn parse_position<G: Game>(notation: Option<&str>) -> G {
+    match notation {
+        Some(notation) => G::from_notation(notation).unwrap_or_else(|err| {
+            eprintln!("Invalid position `{notation}`: {err}");
+            process::exit(1);
+        }),
+        None => G::empty(),
+    }
+}
+
+impl StartPosition {
+    /// Plays the moves on the position from the command line.
+    fn board<G: Game>(&self) -> G {
+        let mut board = parse_position::<G>(self.position.as_deref());
+        for text in &self.moves {
+            let Some(pos) = G::parse_move(text) else {
+                eprintln!("Invalid move `{text}`");
+                process::exit(1);
+            };
+            if let Err(err) = board.try_make_move(pos) {
+                eprintln!("Cannot play {text}, {err}:\n{board}");
+                process::exit(1);
+            }
+        }
+        board
+    }
+}
+
+fn build_database<G: Game>(output: &Path, position: &StartPosition) {
+    let start = SystemTime::now();
+
+    let board = position.board::<G>();
+    let database = Database::build(&board);
+    if let Err(err) = database.save(output) {
+        eprintln!("Could not write the database {}: {err}", output.display());
+        process::exit(1);
+    }
+
+    println!(
+        "Wrote {} positions to {} in {}s",
+        database.len(),
+        output.display(),
+        start.elapsed().unwrap().as_secs()
+    );
+    if let Some(score) = database.get(&board) {
+        println!("{} to move: {score:?}", board.side_to_move());
+    }
+}
+
+fn solve(position: &StartPosition) {
+    let board = position.board::<Connect4>();
+
+    print!("{board}");
+    if board.result() != State::InProgress {
+        println!("The game is over already: {:?}", board.result());
+        return;
+    }
+
+    // Positions of the whole game don't fit into the default table.
+    let mut solver = PerfectPlayer::new(false).with_table_size(1 << 22);
+    let score = solver.solve(&board).expect("nothing stops the solver");
+    println!(
+        "{} to move: {score:?}, best move: {}, expected line: {}",
+        board.side_to_move(),
+        Connect4::format_move(solver.best_move(&board)),
+        solver
+            .principal_variation()
+            .iter()
+            .map(|&mv| Connect4::format_move(mv))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    println!("{}", solver.stats());
+}
+
+fn prove<G: Game>(attacker: Option<Player>, nodes: Option<usize>, position: &StartPosition) {
+    let start = SystemTime::now();
+
+    let board = position.board::<G>();
+    let player = board.side_to_move();
+    let attacker = attacker.unwrap_or(player);
+    print!("{board}");
+
+    let mut search = ProofNumberSearch::new();
+    if let Some(nodes) = nodes {
+        search = search.with_node_limit(nodes);
+    }
+    match search.prove(&board, attacker) {
+        ProofResult::Proven(tree) => {
+            println!("{attacker} can force a win");
+            if let Some((mv, _)) = tree.children.first().filter(|_| player == attacker) {
+                println!("Winning move: {}", G::format_move(*mv));
+            }
+            println!(
+                "Proof tree: {} positions, won in at most {} plies",
+                tree.size(),
+                tree.depth()
+            );
+        }
+        ProofResult::Disproven => println!("{attacker} cannot force a win"),
+        ProofResult::Unknown => println!("Gave up, the position is too hard"),
+    }
+    println!(
+        "Searched {} positions in {}ms",
+        search.nodes(),
+        start.elapsed().unwrap().as_millis()
+    );
+}
+
 #[allow(dead_code)]
 fn tic_tac_toe_stats() {
     let mut results = [0, 0, 0];
@@ -135,8 +523,11 @@ fn tic_tac_toe_stats() {
     println!("Completed in {}ms", time.as_millis());
 }
 
-fn play_with_players<G: Game, X: GamePlayer<G>, O: GamePlayer<G>>(mut x: X, mut o: O) {
-    let mut board = G::empty();
+fn play_with_players<G: Game, X: GamePlayer<G>, O: GamePlayer<G>>(
+    mut board: G,
+    mut x: X,
+    mut o: O,
+) {
     let result = board.play(&mut x, &mut o);
 
     print_result(result, board);
@@ -153,8 +544,9 @@ fn play<X: GamePlayer<G> + Default, O: GamePlayer<G> + Default, G: Game>(
     result
 }
 
-fn print_result(result: Option<Player>, board: impl Display) {
+fn print_result(result: Option<Player>, board: impl Game) {
     println!("{board}");
+    println!("{}", board.to_notation());
 
     match result {
         Some(winner) => {
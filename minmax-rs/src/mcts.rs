@@ -0,0 +1,322 @@
+//! Monte Carlo tree search with UCT.
+//! Instead of rating positions, it plays a lot of random games and looks at who won them,
+//! so it works for every [`Game`], even ones without a good [`Game::rate`].
+
+use std::{
+    mem,
+    time::{Duration, Instant},
+};
+
+use crate::{player::RandomPlayer, Game, GamePlayer, Player, State};
+
+/// The `c` in UCT. Higher values try out more moves, lower values look deeper into the good ones.
+const DEFAULT_EXPLORATION: f64 = std::f64::consts::SQRT_2;
+
+#[derive(Debug, Clone, Copy)]
+enum Budget {
+    Iterations(u64),
+    Time(Duration),
+}
+
+#[derive(Clone)]
+struct Node<M> {
+    /// The move leading to this node, `None` for the root.
+    mv: Option<M>,
+    /// The player that made the move, so the wins are counted from their perspective.
+    player: Player,
+    /// The hash of the board after the move, used to find the position again when reusing the tree.
+    hash: u64,
+    children: Vec<usize>,
+    /// Moves that don't have a node yet. The best one according to the game is last.
+    untried_moves: Vec<M>,
+    visits: u64,
+    /// 1 for every win of `player` and 0.5 for every draw.
+    wins: f64,
+}
+
+#[derive(Clone)]
+pub struct MctsPlayer<G: Game, R = RandomPlayer> {
+    budget: Budget,
+    exploration: f64,
+    rollout_player: R,
+    /// The tree, with the root at index 0. Kept between moves.
+    nodes: Vec<Node<G::Move>>,
+}
+
+impl<G: Game> Default for MctsPlayer<G> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: Game> MctsPlayer<G> {
+    pub fn new() -> Self {
+        Self {
+            budget: Budget::Iterations(10_000),
+            exploration: DEFAULT_EXPLORATION,
+            rollout_player: RandomPlayer,
+            nodes: Vec::new(),
+        }
+    }
+}
+
+impl<G: Game, R: GamePlayer<G>> MctsPlayer<G, R> {
+    pub fn with_iterations(mut self, iterations: u64) -> Self {
+        self.budget = Budget::Iterations(iterations);
+        self
+    }
+
+    pub fn with_time_limit(mut self, time_limit: Duration) -> Self {
+        self.budget = Budget::Time(time_limit);
+        self
+    }
+
+    pub fn with_exploration(mut self, exploration: f64) -> Self {
+        self.exploration = exploration;
+        self
+    }
+
+    /// The player that plays both sides of the simulated games. [`RandomPlayer`] by default.
+    pub fn with_rollout_player<R2: GamePlayer<G>>(self, rollout_player: R2) -> MctsPlayer<G, R2> {
+        MctsPlayer {
+            budget: self.budget,
+            exploration: self.exploration,
+            rollout_player,
+            nodes: Vec::new(),
+        }
+    }
+
+    fn new_node(board: &G, mv: Option<G::Move>) -> Node<G::Move> {
+        let mut untried_moves = match board.result() {
+            State::InProgress => board.possible_moves().collect::<Vec<_>>(),
+            State::Winner(_) | State::Draw => Vec::new(),
+        };
+        untried_moves.reverse();
+
+        Node {
+            mv,
+            player: board.side_to_move().opponent(),
+            hash: board.hash(),
+            children: Vec::new(),
+            untried_moves,
+            visits: 0,
+            wins: 0.0,
+        }
+    }
+
+    /// Finds the current board in the tree of the last move, which should be one of the replies
+    /// to our move. If it's not there, we start over.
+    fn reuse_tree(&mut self, board: &G) {
+        let new_root = match self.nodes.first() {
+            Some(root) if root.hash == board.hash() => Some(0),
+            Some(root) => root
+                .children
+                .iter()
+                .copied()
+                .find(|&child| self.nodes[child].hash == board.hash()),
+            None => None,
+        };
+
+        match new_root {
+            Some(new_root) => self.keep_subtree(new_root),
+            None => {
+                self.nodes.clear();
+                self.nodes.push(Self::new_node(board, None));
+            }
+        }
+    }
+
+    /// Throws away everything except the subtree of `new_root`, which becomes the root.
+    fn keep_subtree(&mut self, new_root: usize) {
+        let mut old_nodes = mem::take(&mut self.nodes)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+
+        let mut root = old_nodes[new_root].take().expect("node moved twice");
+        root.mv = None;
+        self.nodes.push(root);
+
+        // Breadth first, every node moves its children to the end and remembers their new index.
+        let mut i = 0;
+        while i < self.nodes.len() {
+            let children = mem::take(&mut self.nodes[i].children);
+            let mut new_children = Vec::with_capacity(children.len());
+            for child in children {
+                new_children.push(self.nodes.len());
+                let child = old_nodes[child].take().expect("node moved twice");
+                self.nodes.push(child);
+            }
+            self.nodes[i].children = new_children;
+            i += 1;
+        }
+    }
+
+    fn iterate(&mut self, root_board: &G) {
+        let mut board = root_board.clone();
+        let mut path = vec![0];
+        let mut node = 0;
+
+        // Selection: walk down the fully expanded nodes.
+        while self.nodes[node].untried_moves.is_empty() && !self.nodes[node].children.is_empty() {
+            node = self.select_child(node);
+            let child = &self.nodes[node];
+            board.make_move(child.mv.expect("child without move"));
+            path.push(node);
+        }
+
+        // Expansion: add one new node.
+        if let Some(mv) = self.nodes[node].untried_moves.pop() {
+            board.make_move(mv);
+
+            let child = self.nodes.len();
+            self.nodes.push(Self::new_node(&board, Some(mv)));
+            self.nodes[node].children.push(child);
+
+            node = child;
+            path.push(node);
+        }
+
+        // Simulation: play the game until the end.
+        let winner = self.rollout(&mut board);
+
+        // Backpropagation: tell everyone on the path who won.
+        for node in path {
+            let node = &mut self.nodes[node];
+            node.visits += 1;
+            node.wins += match winner {
+                Some(winner) if winner == node.player => 1.0,
+                Some(_) => 0.0,
+                None => 0.5,
+            };
+        }
+    }
+
+    fn select_child(&self, node: usize) -> usize {
+        let parent_visits = (self.nodes[node].visits as f64).ln();
+
+        let uct = |child: usize| {
+            let child = &self.nodes[child];
+            let visits = child.visits as f64;
+            child.wins / visits + self.exploration * (parent_visits / visits).sqrt()
+        };
+
+        self.nodes[node]
+            .children
+            .iter()
+            .copied()
+            .max_by(|&a, &b| uct(a).total_cmp(&uct(b)))
+            .expect("no children")
+    }
+
+    fn rollout(&mut self, board: &mut G) -> Option<Player> {
+        loop {
+            match board.result() {
+                State::Winner(winner) => return Some(winner),
+                State::Draw => return None,
+                State::InProgress => {}
+            }
+
+            let pos = self.rollout_player.next_move(board);
+            board.make_move(pos);
+        }
+    }
+
+    /// The most visited child, which is more reliable than the one with the best win rate.
+    fn best_child(&self) -> usize {
+        self.nodes[0]
+            .children
+            .iter()
+            .copied()
+            .max_by_key(|&child| self.nodes[child].visits)
+            .expect("cannot make move")
+    }
+}
+
+impl<G: Game, R: GamePlayer<G>> GamePlayer<G> for MctsPlayer<G, R> {
+    fn next_move(&mut self, board: &G) -> G::Move {
+        self.reuse_tree(board);
+
+        match self.budget {
+            Budget::Iterations(iterations) => {
+                for _ in 0..iterations {
+                    self.iterate(board);
+                }
+            }
+            Budget::Time(time_limit) => {
+                let deadline = Instant::now() + time_limit;
+                // Always do at least one iteration, otherwise there's no move to pick.
+                loop {
+                    self.iterate(board);
+                    if Instant::now() >= deadline {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let best_child = self.best_child();
+        let best_move = self.nodes[best_child].mv.expect("child without move");
+
+        // The opponent's reply will be one of the children of our move.
+        self.keep_subtree(best_child);
+        best_move
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::connect4::board::Connect4;
+    use crate::tic_tac_toe::TicTacToe;
+    use crate::{Game, GamePlayer, Player, State};
+
+    use super::MctsPlayer;
+
+    fn three_in_a_row() -> Connect4 {
+        let mut board = Connect4::empty();
+        for pos in [0, 1, 2] {
+            board.make_move(pos);
+            board.make_move(pos);
+        }
+        board
+    }
+
+    #[test]
+    fn takes_the_win() {
+        let mut board = three_in_a_row();
+
+        let pos = MctsPlayer::new().with_iterations(2000).next_move(&board);
+        board.make_move(pos);
+
+        assert_eq!(board.result(), State::Winner(Player::X));
+    }
+
+    #[test]
+    fn blocks_the_loss() {
+        let mut board = three_in_a_row();
+        board.make_move(6);
+
+        let pos = MctsPlayer::new().with_iterations(2000).next_move(&board);
+        board.make_move(pos);
+        board.make_move(3);
+
+        assert_eq!(board.result(), State::InProgress);
+    }
+
+    #[test]
+    fn reuses_tree() {
+        let mut board = TicTacToe::empty();
+        let mut player = MctsPlayer::new().with_iterations(1000);
+
+        let pos = player.next_move(&board);
+        board.make_move(pos);
+        let reply = board.possible_moves().next().unwrap();
+        board.make_move(reply);
+
+        // The reply was explored, so its subtree is kept.
+        player.reuse_tree(&board);
+        assert_eq!(player.nodes[0].hash, board.hash());
+        assert!(player.nodes[0].visits > 0);
+        assert!(player.nodes.len() > 1);
+    }
+}
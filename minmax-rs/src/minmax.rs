@@ -1,18 +1,127 @@
 //! The core logic of the minmax algorithm.
 //! It's generic over [`Game`] and works with every game.
 
-use std::time::Instant;
+use std::{
+    cmp::Reverse,
+    mem,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
 
 use crate::{
-    state::{GoodPlayer, MinmaxPlayer},
+    background::StopHandle,
+    state::{EvilPlayer, GoodPlayer, IgnorePlayer, MinmaxPlayer},
+    stats::SearchStats,
+    transposition::{self, Bound, Entry, TranspositionTable},
     Game, GamePlayer, Player, Score, State,
 };
 
+/// About 24MB for Connect4.
+const DEFAULT_TABLE_SIZE: usize = 1 << 20;
+
+/// Looking at the clock for every node would be too slow. Must be a power of two.
+const NODES_BETWEEN_TIME_CHECKS: u64 = 1024;
+
+/// How far the aspiration window of [`SearchAlgorithm::Pvs`] reaches to both sides of the score
+/// of the last iteration, in the units of [`Game::rate`].
+const ASPIRATION_WINDOW: i32 = 8;
+
+/// The max depth, evaluation noise and chance of a bad move for the skill levels 1 to 9.
+/// Level 10 is the normal player.
+const SKILL_LEVELS: [(usize, i32, f64); 9] = [
+    (1, 8, 0.5),
+    (2, 6, 0.4),
+    (2, 4, 0.3),
+    (3, 4, 0.25),
+    (4, 3, 0.2),
+    (5, 2, 0.15),
+    (6, 2, 0.1),
+    (7, 1, 0.05),
+    (9, 0, 0.02),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchAlgorithm {
+    /// Alpha-beta pruning, searching every move with the window of its parent.
+    #[default]
+    AlphaBeta,
+    /// Principal variation search, also known as NegaScout. Only the first move is searched with
+    /// the full window, the others just have to prove that they're not better with a null window,
+    /// which is a lot faster if the move ordering is good. Iterative deepening starts every
+    /// iteration with an aspiration window around the score of the last one.
+    Pvs,
+}
+
 #[derive(Clone)]
 pub struct PerfectPlayer<G: Game> {
     best_move: Option<G::Move>,
+    /// The score of `best_move`. Only meaningful after a search.
+    best_score: Score<IgnorePlayer>,
+    /// The moves that the last search expects both players to make, starting with `best_move`.
+    principal_variation: Vec<G::Move>,
+    /// The lines of all root moves, if the last search scored every one of them.
+    root_variations: Vec<(G::Move, Vec<G::Move>)>,
+    /// The best line below every ply of the current search. A node that gets an exact score
+    /// copies the line of its best child behind its move.
+    pv_table: Vec<Vec<G::Move>>,
     max_depth: Option<usize>,
+    time_limit: Option<Duration>,
+    node_limit: Option<u64>,
+    algorithm: SearchAlgorithm,
+    /// Pick randomly between the root moves that are at most this much worse than the best one.
+    variety: Option<u32>,
+    /// Added to every [`Game::rate`] in either direction, so that the player misjudges positions.
+    eval_noise: i32,
+    /// The evaluation noise of a position only depends on this seed and the position, so
+    /// transpositions and the helpers see the same noise. Drawn from `rng` for every move.
+    noise_seed: u64,
+    /// The chance to make a random move that's worse than the best one.
+    blunder_chance: f64,
+    rng: StdRng,
+    threads: usize,
+    /// The players searching on the other threads. Kept around to keep their transposition tables.
+    helpers: Vec<PerfectPlayer<G>>,
     print_time: bool,
+    table_size: usize,
+    // Only allocated once we start searching, a lot of players are created and never used.
+    transposition_table: Option<TranspositionTable<G::Move>>,
+    /// The depth of the current search. Differs from `max_depth` while iteratively deepening.
+    depth_limit: Option<usize>,
+    /// Whether the subtree currently being searched was cut off by the depth limit anywhere.
+    /// If it wasn't, its score is exact and doesn't depend on the depth.
+    reached_depth_limit: bool,
+    deadline: Option<Instant>,
+    /// Shared with the helpers, so stopping one stops them all.
+    stop: StopHandle,
+    aborted: bool,
+    stats: SearchStats,
+    /// Per ply, the last two moves that caused a cutoff. The positions next to each other
+    /// in the tree are similar, so they'll likely cause one there too.
+    killers: Vec<[Option<G::Move>; 2]>,
+    /// How good every move of a player was at causing cutoffs anywhere in the tree,
+    /// by [`Player::as_u8`] and [`Game::move_index`].
+    history: [Vec<u64>; 2],
+    /// Per ply, the moves in the order they're searched in. Kept around, allocating at every node
+    /// would be too slow.
+    move_buffers: Vec<Vec<G::Move>>,
+    /// Whether the killer moves and the history order the moves. Only turned off to measure
+    /// how much they save.
+    use_history: bool,
+}
+
+/// The best root move of a search with several threads so far.
+struct RootBest<M> {
+    /// The alpha of the root window until a move beats it.
+    score: Score<GoodPlayer>,
+    /// In the order the root moves are searched in.
+    index: Option<usize>,
+    line: Vec<M>,
 }
 
 impl<G: Game> Default for PerfectPlayer<G> {
@@ -25,52 +134,820 @@ impl<G: Game> PerfectPlayer<G> {
     pub fn new(print_time: bool) -> Self {
         Self {
             best_move: None,
+            best_score: Score::TIE,
+            principal_variation: Vec::new(),
+            root_variations: Vec::new(),
+            pv_table: Vec::new(),
             max_depth: G::REASONABLE_SEARCH_DEPTH,
+            time_limit: None,
+            node_limit: None,
+            algorithm: SearchAlgorithm::default(),
+            variety: None,
+            eval_noise: 0,
+            noise_seed: 0,
+            blunder_chance: 0.0,
+            rng: StdRng::from_entropy(),
+            threads: 1,
+            helpers: Vec::new(),
             print_time,
+            table_size: DEFAULT_TABLE_SIZE,
+            transposition_table: None,
+            depth_limit: None,
+            reached_depth_limit: false,
+            deadline: None,
+            stop: StopHandle::new(),
+            aborted: false,
+            stats: SearchStats::default(),
+            killers: Vec::new(),
+            history: [vec![0; G::MOVE_COUNT], vec![0; G::MOVE_COUNT]],
+            move_buffers: Vec::new(),
+            use_history: true,
         }
     }
 
+    /// With `None`, the player searches until the end of every game like [`PerfectPlayer::solve`],
+    /// so it really is perfect. That's only fast enough for small games or late positions.
     pub fn with_max_depth(mut self, max_depth: Option<usize>) -> Self {
         self.max_depth = max_depth;
         self
     }
 
+    /// Search iteratively deeper until the time runs out and play the best move of the deepest
+    /// completed search. The max depth is still respected, so use `with_max_depth(None)` to
+    /// only be limited by time.
+    pub fn with_time_limit(mut self, time_limit: Duration) -> Self {
+        self.time_limit = Some(time_limit);
+        self
+    }
+
+    /// Like a time limit, but counts the searched positions instead of the time. The same position
+    /// gets the same move on every machine. How the threads split the moves depends on their timing,
+    /// so a search with a node limit only uses one thread.
+    pub fn with_node_limit(mut self, node_limit: u64) -> Self {
+        self.node_limit = Some(node_limit);
+        self
+    }
+
+    pub fn with_algorithm(mut self, algorithm: SearchAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self.helpers.clear();
+        self
+    }
+
+    /// Makes a random one of the moves whose score is at most `margin` worse than the best score,
+    /// instead of always the first of the best moves. With a margin of 0, it only picks between
+    /// moves that are equally good. Every root move has to be searched with the full window for
+    /// this, so it's slower.
+    pub fn with_variety(mut self, margin: u32) -> Self {
+        self.variety = Some(margin);
+        self
+    }
+
+    /// Makes the player weaker, from 1 (a beginner) to 10 (the normal player). Lower levels search
+    /// less deep, misjudge positions and sometimes make a bad move on purpose.
+    /// Overrides the max depth, so set a different one afterwards.
+    pub fn with_skill(mut self, level: u8) -> Self {
+        assert!(
+            (1..=10).contains(&level),
+            "the skill level must be between 1 and 10"
+        );
+        (self.max_depth, self.eval_noise, self.blunder_chance) = match level {
+            10 => (G::REASONABLE_SEARCH_DEPTH, 0, 0.0),
+            _ => {
+                let (max_depth, eval_noise, blunder_chance) = SKILL_LEVELS[usize::from(level) - 1];
+                (Some(max_depth), eval_noise, blunder_chance)
+            }
+        };
+        self.helpers.clear();
+        self
+    }
+
+    /// Makes the random choices repeatable.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Search with `threads` threads. The first root move is searched alone, then the others are
+    /// split between the threads, which share the best score found so far. Using more threads than
+    /// there are moves doesn't help. Every thread has its own transposition table.
+    /// Ignored with a node limit, see [`PerfectPlayer::with_node_limit`].
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        assert!(threads > 0, "cannot search with zero threads");
+        self.threads = threads;
+        self.helpers.clear();
+        self
+    }
+
+    /// The number of positions the transposition table can hold. Rounded up to a power of two.
+    pub fn with_table_size(mut self, table_size: usize) -> Self {
+        self.table_size = table_size;
+        self.transposition_table = None;
+        self.helpers.clear();
+        self
+    }
+
+    /// Lets another thread stop the search. A stopped search makes the best move it found so far.
+    /// The handle stays stopped until it's [reset](StopHandle::reset).
+    pub fn with_stop_handle(mut self, stop: StopHandle) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    pub(crate) fn time_limit(&self) -> Option<Duration> {
+        self.time_limit
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// The numbers of the last call to [`GamePlayer::next_move`] or [`PerfectPlayer::analyze`].
+    pub fn stats(&self) -> &SearchStats {
+        &self.stats
+    }
+
     pub fn best_move(&self, board: &G) -> G::Move {
         self.best_move
             .unwrap_or_else(|| board.possible_moves().next().expect("cannot make move"))
     }
 
+    /// Searches the board up to `depth_limit`. Returns `false` if the search ran out of time.
+    fn search(&mut self, board: &mut G, depth_limit: Option<usize>) -> bool {
+        self.search_window(board, depth_limit, Score::LOST, Score::WON)
+    }
+
+    /// Searches without a depth limit, faster than [`PerfectPlayer::search`] would.
+    fn search_to_end(&mut self, board: &mut G) -> bool {
+        // Without a depth limit, every score is a win, a loss or a draw. Finding out which one
+        // only needs the tiny window around a draw and is a lot faster than the full search.
+        if !self.search_window(board, None, Score::new(-1), Score::new(1)) {
+            return false;
+        }
+        // The distance to the end of the game needs another search, but only among the wins
+        // or the losses.
+        if self.best_score > Score::TIE {
+            self.search_window(board, None, Score::TIE, Score::WON)
+        } else if self.best_score < Score::TIE {
+            self.search_window(board, None, Score::LOST, Score::TIE)
+        } else {
+            true
+        }
+    }
+
+    /// Like [`PerfectPlayer::search`], but only looks for scores between `alpha` and `beta`.
+    /// If the real score is outside, we get `alpha` or `beta` instead, and maybe no best move.
+    /// Searches that score every root move always use the full window.
+    fn search_window(
+        &mut self,
+        board: &mut G,
+        depth_limit: Option<usize>,
+        alpha: Score<IgnorePlayer>,
+        beta: Score<IgnorePlayer>,
+    ) -> bool {
+        if self.scores_every_root_move() {
+            return self.search_every_root_move(board, depth_limit);
+        }
+
+        self.start_search(depth_limit);
+
+        // Get the rating for the one move we will make.
+        let (alpha, beta) = (alpha.for_player::<GoodPlayer>(), beta.for_player());
+        self.best_score = if self.threads() > 1 {
+            self.search_in_parallel(board, alpha, beta)
+        } else {
+            self.minmax::<GoodPlayer>(board, alpha, beta, 0)
+        }
+        .ignore_side();
+
+        if self.aborted {
+            return false;
+        }
+        self.principal_variation = self.pv_table[0].clone();
+        true
+    }
+
+    /// Searches with a small window around the score of the last iteration first. Most of the time
+    /// the score doesn't change much, and if it does, we search again with the full window.
+    fn search_aspiration(
+        &mut self,
+        board: &mut G,
+        depth_limit: Option<usize>,
+        last_score: Score<IgnorePlayer>,
+    ) -> bool {
+        // Won and lost scores jump around too much, and the full window is as good as any there.
+        let close_to_last =
+            last_score.plies_to_win().is_none() && last_score.plies_to_loss().is_none();
+        if self.algorithm == SearchAlgorithm::Pvs && !self.scores_every_root_move() && close_to_last
+        {
+            let alpha = Score::new(last_score.0.saturating_sub(ASPIRATION_WINDOW));
+            let beta = Score::new(last_score.0.saturating_add(ASPIRATION_WINDOW));
+            if !self.search_window(board, depth_limit, alpha, beta) {
+                return false;
+            }
+            if alpha < self.best_score && self.best_score < beta {
+                return true;
+            }
+            self.stats.re_searches += 1;
+        }
+
+        self.search(board, depth_limit)
+    }
+
+    /// Forgets what only mattered for the last move.
+    fn start_move(&mut self) {
+        self.stats = SearchStats::default();
+        self.principal_variation.clear();
+        self.root_variations.clear();
+        self.noise_seed = self.rng.gen();
+
+        // The plies are counted from the root, so the killers belong to other positions now.
+        self.killers.clear();
+        // The history is still useful, but the new search should be able to overrule it.
+        for score in self.history.iter_mut().flatten() {
+            *score /= 2;
+        }
+    }
+
+    fn start_search(&mut self, depth_limit: Option<usize>) {
+        self.best_move = None;
+        self.depth_limit = depth_limit;
+        self.reached_depth_limit = false;
+        self.aborted = false;
+    }
+
+    /// Returns the score of every possible move, in the order of [`Game::possible_moves`].
+    /// The scores are exact, unlike during a normal search where most moves only get a bound.
+    /// Returns `None` if the search was stopped or hit the node limit, the scores of a part of the
+    /// moves would be misleading.
+    pub fn analyze(&mut self, board: &G) -> Option<Vec<(G::Move, Score<IgnorePlayer>)>> {
+        let start = Instant::now();
+        self.start_move();
+
+        let scores = self.score_root_moves(board, self.max_depth);
+
+        self.stats.duration = start.elapsed();
+
+        scores.map(|scores| {
+            scores
+                .into_iter()
+                .map(|(pos, score)| (pos, score.ignore_side()))
+                .collect()
+        })
+    }
+
+    /// Searches until the end of the game, however long that takes, so the score is the exact
+    /// game-theoretic value: won, lost or drawn, and in how many plies. The max depth, the skill
+    /// level and the variety don't matter here. Afterwards, [`PerfectPlayer::best_move`] is a move that
+    /// gets this score. Returns `None` if the search was stopped or hit the node limit.
+    pub fn solve(&mut self, board: &G) -> Option<Score<IgnorePlayer>> {
+        let start = Instant::now();
+        self.start_move();
+
+        let mut board = board.clone();
+        // Both would pick a worse move than the best one on purpose.
+        let variety = self.variety.take();
+        let blunder_chance = mem::replace(&mut self.blunder_chance, 0.0);
+        let solved = self.search_to_end(&mut board);
+        self.variety = variety;
+        self.blunder_chance = blunder_chance;
+
+        self.stats.duration = start.elapsed();
+
+        solved.then_some(self.best_score)
+    }
+
+    /// The moves that the last search expects both players to make, starting with the move it made.
+    pub fn principal_variation(&self) -> &[G::Move] {
+        &self.principal_variation
+    }
+
+    /// The moves that the last search expects both players to make after `first_move`,
+    /// starting with `first_move`. Every root move has one after [`PerfectPlayer::analyze`] and
+    /// searches that score every root move, otherwise only the best move does.
+    /// Empty for moves without one.
+    pub fn variation(&self, first_move: G::Move) -> &[G::Move] {
+        if self.principal_variation.first() == Some(&first_move) {
+            return &self.principal_variation;
+        }
+        self.root_variations
+            .iter()
+            .find(|(pos, _)| *pos == first_move)
+            .map_or(&[], |(_, line)| line.as_slice())
+    }
+
+    /// The number of threads the search actually uses.
+    fn threads(&self) -> usize {
+        if self.node_limit.is_some() {
+            1
+        } else {
+            self.threads
+        }
+    }
+
+    /// Picking from several good moves needs the exact score of every root move, not just of the
+    /// best one.
+    fn scores_every_root_move(&self) -> bool {
+        self.variety.is_some() || self.blunder_chance > 0.0
+    }
+
+    /// Every root move is searched with the full window instead of the best score so far. That's
+    /// slower, but we get exact scores and pick the same move as the sequential search would.
+    fn search_every_root_move(&mut self, board: &G, depth_limit: Option<usize>) -> bool {
+        let table_move = self.root_table_move(board);
+        let Some(scores) = self.score_root_moves(board, depth_limit) else {
+            return false;
+        };
+
+        // The sequential search picks the first of the best moves in the order of
+        // `ordered_moves`, so we do too.
+        let searched_first = scores.iter().filter(|&&(pos, _)| Some(pos) == table_move);
+        let others = scores.iter().filter(|&&(pos, _)| Some(pos) != table_move);
+        let mut max_value = None;
+        for &(pos, score) in searched_first.chain(others) {
+            if Some(score) > max_value {
+                max_value = Some(score);
+                self.best_move = Some(pos);
+                self.best_score = score.ignore_side();
+            }
+        }
+        let best_score = self.best_score.for_player();
+        self.remember_root(board, best_score, Bound::Exact);
+
+        if let Some(margin) = self.variety {
+            let good_enough = self
+                .best_score
+                .0
+                .saturating_sub(i32::try_from(margin).unwrap_or(i32::MAX));
+            let candidates = scores
+                .iter()
+                .filter(|(_, score)| score.0 >= good_enough)
+                .collect::<Vec<_>>();
+            if let Some(&&(pos, score)) = candidates.choose(&mut self.rng) {
+                self.best_move = Some(pos);
+                self.best_score = score.ignore_side();
+            }
+        }
+
+        if self.blunder_chance > 0.0 && self.rng.gen_bool(self.blunder_chance) {
+            let best_score = self.best_score;
+            let worse = scores
+                .iter()
+                .filter(|(_, score)| score.ignore_side() < best_score)
+                .collect::<Vec<_>>();
+            if let Some(&&(pos, score)) = worse.choose(&mut self.rng) {
+                self.best_move = Some(pos);
+                self.best_score = score.ignore_side();
+            }
+        }
+
+        self.principal_variation = self
+            .root_variations
+            .iter()
+            .find(|&&(pos, _)| Some(pos) == self.best_move)
+            .map(|(_, line)| line.clone())
+            .unwrap_or_default();
+        true
+    }
+
+    /// The best move of the last search of this board, which the sequential search tries first.
+    fn root_table_move(&mut self, board: &G) -> Option<G::Move> {
+        let (hash, symmetry) = board.canonical_hash();
+        let key = transposition::key(hash, board.side_to_move());
+        let entry = self.table().get(key).copied()?;
+        entry
+            .best_move
+            .map(|pos| G::untransform_move(pos, symmetry))
+    }
+
+    /// Stores the best root move like the sequential search does, so that both try it first
+    /// the next time.
+    fn remember_root(&mut self, board: &G, score: Score<GoodPlayer>, bound: Bound) {
+        let (hash, symmetry) = board.canonical_hash();
+        let remaining_depth = self
+            .depth_limit
+            .map_or(u8::MAX, |max_depth| max_depth.min(u8::MAX.into()) as u8);
+        let entry = Entry {
+            key: transposition::key(hash, board.side_to_move()),
+            score: score.to_table(0),
+            bound,
+            depth: if self.reached_depth_limit {
+                remaining_depth
+            } else {
+                u8::MAX
+            },
+            best_move: self.best_move.map(|pos| G::transform_move(pos, symmetry)),
+        };
+        self.table().insert(entry);
+    }
+
+    /// Gets the helpers ready for the search of every thread but this one.
+    fn start_helpers(&mut self) -> Vec<PerfectPlayer<G>> {
+        let mut helpers = mem::take(&mut self.helpers);
+        helpers.resize_with(self.threads() - 1, || {
+            PerfectPlayer::new(false)
+                .with_max_depth(self.max_depth)
+                .with_algorithm(self.algorithm)
+                .with_table_size(self.table_size)
+        });
+        for helper in &mut helpers {
+            helper.start_search(self.depth_limit);
+            helper.deadline = self.deadline;
+            helper.stop = self.stop.clone();
+            helper.eval_noise = self.eval_noise;
+            helper.noise_seed = self.noise_seed;
+            // What worked for this thread so far is the best guess for the others too.
+            helper.killers.clone_from(&self.killers);
+            helper.history.clone_from(&self.history);
+        }
+        helpers
+    }
+
+    /// Takes over what the helpers found out and keeps them for the next search.
+    fn finish_helpers(&mut self, mut helpers: Vec<PerfectPlayer<G>>) {
+        for helper in &mut helpers {
+            self.aborted |= helper.aborted;
+            self.reached_depth_limit |= helper.reached_depth_limit;
+            self.stats.merge(&mem::take(&mut helper.stats));
+        }
+        self.helpers = helpers;
+    }
+
+    /// The root of [`PerfectPlayer::minmax`], split between all threads. The first move is searched
+    /// alone, it's usually the best one and gives the others a good score to beat. Then every thread
+    /// takes the next root move that nobody has searched yet until there are none left, and only
+    /// looks for scores that are better than the best one any thread found so far.
+    fn search_in_parallel(
+        &mut self,
+        board: &mut G,
+        alpha: Score<GoodPlayer>,
+        beta: Score<GoodPlayer>,
+    ) -> Score<GoodPlayer> {
+        if board.result() != State::InProgress {
+            // Nothing to split.
+            return self.minmax::<GoodPlayer>(board, alpha, beta, 0);
+        }
+        self.stats.count_node(0);
+        if self.pv_table.is_empty() {
+            self.pv_table.push(Vec::new());
+        }
+
+        let table_move = self.root_table_move(board);
+        let moves = self.ordered_moves(board, board.side_to_move(), 0, table_move);
+
+        let best = Mutex::new(RootBest {
+            score: alpha,
+            index: None,
+            line: Vec::new(),
+        });
+        self.search_root_moves_against(board, &moves[..1], beta, &AtomicUsize::new(0), &best);
+        if self.aborted {
+            return alpha;
+        }
+
+        let next_move = AtomicUsize::new(1);
+        let mut helpers = self.start_helpers();
+        thread::scope(|scope| {
+            let board = &*board;
+            for helper in &mut helpers {
+                let (moves, next_move, best) = (&moves, &next_move, &best);
+                scope.spawn(move || {
+                    helper.search_root_moves_against(board, moves, beta, next_move, best)
+                });
+            }
+            self.search_root_moves_against(board, &moves, beta, &next_move, &best);
+        });
+        self.finish_helpers(helpers);
+        if self.aborted {
+            return alpha;
+        }
+
+        let best = best.into_inner().expect("a search thread panicked");
+        self.best_move = best.index.map(|index| moves[index]);
+        self.pv_table[0] = best.line;
+        let bound = if best.score <= alpha {
+            Bound::Upper
+        } else if best.score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.remember_root(board, best.score, bound);
+        best.score
+    }
+
+    /// Searches root moves for [`PerfectPlayer::search_in_parallel`] until there are none left
+    /// or one of them causes a cutoff.
+    fn search_root_moves_against(
+        &mut self,
+        board: &G,
+        moves: &[G::Move],
+        beta: Score<GoodPlayer>,
+        next_move: &AtomicUsize,
+        best: &Mutex<RootBest<G::Move>>,
+    ) {
+        let mut board = board.clone();
+        loop {
+            let index = next_move.fetch_add(1, Ordering::Relaxed);
+            let Some(&pos) = moves.get(index) else {
+                return;
+            };
+            let alpha = {
+                let best = best.lock().expect("a search thread panicked");
+                if best.score >= beta {
+                    return;
+                }
+                // The sequential search picks the first of the best moves, so a move that comes
+                // earlier also wins with the same score.
+                match best.index {
+                    Some(best_index) if index < best_index => best.score.just_below(),
+                    _ => best.score,
+                }
+            };
+
+            board.make_move(pos);
+            let null_window = self.algorithm == SearchAlgorithm::Pvs && index > 0;
+            let mut value = -self.minmax::<EvilPlayer>(
+                &mut board,
+                if null_window {
+                    -alpha.just_above()
+                } else {
+                    -beta
+                },
+                -alpha,
+                1,
+            );
+            if null_window && !self.aborted && value > alpha && value < beta {
+                self.stats.re_searches += 1;
+                value = -self.minmax::<EvilPlayer>(&mut board, -beta, -alpha, 1);
+            }
+            board.undo_move(pos);
+
+            if self.aborted {
+                return;
+            }
+            if value <= alpha {
+                continue;
+            }
+
+            let mut best = best.lock().expect("a search thread panicked");
+            let earlier = matches!(best.index, Some(best_index) if index < best_index);
+            if value > best.score || (value == best.score && earlier) {
+                best.score = value;
+                best.index = Some(index);
+                best.line.clear();
+                best.line.push(pos);
+                // Like in `minmax`, a cutoff doesn't get a line.
+                if value < beta {
+                    best.line.extend_from_slice(&self.pv_table[1]);
+                }
+            }
+        }
+    }
+
+    /// Searches every root move with the full window, split between all threads.
+    /// Returns `None` if the search ran out of time.
+    fn score_root_moves(
+        &mut self,
+        board: &G,
+        depth_limit: Option<usize>,
+    ) -> Option<Vec<(G::Move, Score<GoodPlayer>)>> {
+        let moves = board.possible_moves().collect::<Vec<_>>();
+        let next_move = AtomicUsize::new(0);
+
+        self.start_search(depth_limit);
+        // The root isn't searched by `minmax` here, but it's still a node.
+        self.stats.count_node(0);
+        let mut helpers = self.start_helpers();
+
+        let mut scores = thread::scope(|scope| {
+            let handles = helpers
+                .iter_mut()
+                .map(|helper| {
+                    let (moves, next_move) = (&moves, &next_move);
+                    scope.spawn(move || helper.search_root_moves(board, moves, next_move))
+                })
+                .collect::<Vec<_>>();
+
+            let mut scores = self.search_root_moves(board, &moves, &next_move);
+            for handle in handles {
+                scores.extend(handle.join().expect("search thread panicked"));
+            }
+            scores
+        });
+
+        self.finish_helpers(helpers);
+
+        if self.aborted {
+            return None;
+        }
+
+        scores.sort_by_key(|&(index, _, _)| index);
+        let mut root_scores = Vec::with_capacity(scores.len());
+        self.root_variations.clear();
+        for (index, score, line) in scores {
+            root_scores.push((moves[index], score));
+            self.root_variations.push((moves[index], line));
+        }
+        Some(root_scores)
+    }
+
+    fn search_root_moves(
+        &mut self,
+        board: &G,
+        moves: &[G::Move],
+        next_move: &AtomicUsize,
+    ) -> Vec<(usize, Score<GoodPlayer>, Vec<G::Move>)> {
+        let mut board = board.clone();
+        let mut scores = Vec::new();
+
+        loop {
+            let index = next_move.fetch_add(1, Ordering::Relaxed);
+            let Some(&pos) = moves.get(index) else {
+                return scores;
+            };
+
+            board.make_move(pos);
+            let value = -self.minmax::<EvilPlayer>(
+                &mut board,
+                Score::LOST.for_player::<EvilPlayer>(),
+                Score::WON.for_player::<EvilPlayer>(),
+                1,
+            );
+            board.undo_move(pos);
+
+            if self.aborted {
+                return scores;
+            }
+            // The full window always gets an exact score, so there always is a line.
+            let mut line = vec![pos];
+            line.extend_from_slice(&self.pv_table[1]);
+            scores.push((index, value, line));
+        }
+    }
+
+    /// Searches deeper and deeper until the deadline, calling `report` with the best move of every
+    /// finished iteration.
+    fn search_iteratively(
+        &mut self,
+        board: &mut G,
+        deadline: Option<Instant>,
+        mut report: impl FnMut(G::Move, Score<IgnorePlayer>),
+    ) {
+        self.deadline = deadline;
+
+        // Every search orders the moves with the results of the previous one from the
+        // transposition table, so the deeper searches are a lot faster than they would be alone.
+        let mut best_move = None;
+        let mut best_score = Score::TIE;
+        let mut principal_variation = Vec::new();
+        for depth in 1.. {
+            if !self.search_aspiration(board, Some(depth), best_score) {
+                break;
+            }
+            best_move = self.best_move;
+            best_score = self.best_score;
+            principal_variation.clone_from(&self.principal_variation);
+            if let Some(best_move) = best_move {
+                report(best_move, best_score);
+            }
+
+            // Nothing left to discover if the whole game tree was searched.
+            let reached_max_depth = matches!(self.max_depth, Some(max_depth) if depth >= max_depth);
+            if !self.reached_depth_limit || reached_max_depth {
+                break;
+            }
+        }
+
+        // The aborted search may have found a new best move, but it didn't look at all the others.
+        self.best_move = best_move;
+        self.best_score = best_score;
+        self.principal_variation = principal_variation;
+        self.deadline = None;
+        self.aborted = false;
+    }
+
+    /// Searches iteratively until the deadline, for the searches in the background.
+    pub(crate) fn search_until(
+        &mut self,
+        board: &mut G,
+        deadline: Option<Instant>,
+        report: impl FnMut(G::Move, Score<IgnorePlayer>),
+    ) {
+        let start = Instant::now();
+        self.start_move();
+
+        self.search_iteratively(board, deadline, report);
+
+        self.stats.duration = start.elapsed();
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if matches!(self.node_limit, Some(node_limit) if self.stats.nodes >= node_limit) {
+            self.aborted = true;
+        }
+        if self.stats.nodes & (NODES_BETWEEN_TIME_CHECKS - 1) == 0 {
+            let past_deadline =
+                matches!(self.deadline, Some(deadline) if Instant::now() >= deadline);
+            if past_deadline || self.stop.is_stopped() {
+                self.aborted = true;
+            }
+        }
+        self.aborted
+    }
+
     fn minmax<P: MinmaxPlayer>(
         &mut self,
         board: &mut G,
-        maximizing_player: Player,
         grandparents_favourite_child_alpha: Score<P>,
         parents_favourite_child_beta: Score<P>,
         depth: usize,
     ) -> Score<P> {
-        // FIXME: Make depth decrease not increase.
-        if let Some(max_depth) = self.max_depth && depth >= max_depth {
-            return board.rate(maximizing_player).for_player::<P>();
+        self.stats.count_node(depth);
+        let maximizing_player = board.side_to_move();
+        // Nodes that return before searching their moves have no line.
+        if self.pv_table.len() <= depth {
+            self.pv_table.resize_with(depth + 1, Vec::new);
+        }
+        self.pv_table[depth].clear();
+
+        if self.should_stop() {
+            // The score doesn't matter, the whole search will be thrown away.
+            return Score::TIE.for_player::<P>();
         }
 
         match board.result() {
             State::Winner(winner) => {
+                // The depth is the number of plies from the root, so quick wins and slow losses
+                // are better than slow wins and quick losses.
                 if winner == maximizing_player {
                     // Our maximizing player wins the game, so this node is a win for it.
-                    Score::WON.for_player::<P>()
+                    Score::won_in(depth).for_player::<P>()
                 } else {
                     // The maximizing player lost the board here, so the node is a loss.
-                    Score::LOST.for_player::<P>()
+                    Score::lost_in(depth).for_player::<P>()
                 }
             }
             State::Draw => Score::TIE.for_player::<P>(),
             State::InProgress => {
-                // The board isn't done yet, go deeper!
+                // FIXME: Make depth decrease not increase.
+                if let Some(max_depth) = self.depth_limit && depth >= max_depth {
+                    self.reached_depth_limit = true;
+                    self.stats.leaf_evaluations += 1;
+                    return self
+                        .noisy_rating(board, maximizing_player)
+                        .for_player::<P>();
+                }
+
+                // The board isn't done yet, go deeper! But maybe we have been here before.
+                // Symmetric positions share their entry, with the moves of the canonical board.
+                let (hash, symmetry) = board.canonical_hash();
+                let key = transposition::key(hash, maximizing_player);
+                let remaining_depth = self.depth_limit.map_or(u8::MAX, |max_depth| {
+                    (max_depth - depth).min(u8::MAX.into()) as u8
+                });
+
+                let mut table_move = None;
+                if let Some(entry) = self.table().get(key).copied() {
+                    table_move = entry
+                        .best_move
+                        .map(|pos| G::untransform_move(pos, symmetry));
+
+                    // The root must always be searched, we need the move, not just the score.
+                    // A shallower search isn't good enough either, it could have missed something.
+                    if depth > 0 && entry.depth >= remaining_depth {
+                        if entry.depth != u8::MAX {
+                            self.reached_depth_limit = true;
+                        }
+                        let score = Score::from_table(entry.score, depth);
+                        let usable = match entry.bound {
+                            // Inside the window, the score would be part of the principal
+                            // variation, and we only get its line by searching.
+                            Bound::Exact => {
+                                score <= grandparents_favourite_child_alpha
+                                    || score >= parents_favourite_child_beta
+                            }
+                            Bound::Lower => score >= parents_favourite_child_beta,
+                            Bound::Upper => score <= grandparents_favourite_child_alpha,
+                        };
+                        if usable {
+                            self.stats.table_hits += 1;
+                            return score;
+                        }
+                    }
+                }
+
+                // Only track our own subtree, the parent's flag is merged back in below.
+                let parent_reached_depth_limit = mem::replace(&mut self.reached_depth_limit, false);
+
                 // The alpha is the favourite (highest reward) child of our grandparent (who's on our side!).
                 let mut max_value = grandparents_favourite_child_alpha;
+                let mut best_move = None;
 
-                for pos in board.possible_moves() {
-                    board.make_move(pos, maximizing_player);
+                let moves = self.ordered_moves(board, maximizing_player, depth, table_move);
+
+                for (i, &pos) in moves.iter().enumerate() {
+                    board.make_move(pos);
 
                     // Calculate the move for the nested call. This goes one layer deeper.
                     // The number represents the *return* value of the node.
@@ -81,26 +958,55 @@ impl<G: Game> PerfectPlayer<G> {
                     // O A(10) B(  ) <- we are here in the loop and about to call D
                     //          /  \
                     // X    C(11) D(  )
-                    let value = -self.minmax::<P::Enemy>(
+                    //
+                    // With PVS, we expect the first move to be the best, so for all the others we only
+                    // ask whether they're better than it at all. That's the window from max_value to
+                    // just above it, and a narrow window causes a lot of cutoffs.
+                    let null_window = self.algorithm == SearchAlgorithm::Pvs && i > 0;
+                    let mut value = -self.minmax::<P::Enemy>(
                         board,
-                        // The player that will maximize this round is now the opponent. This layer it was our original
-                        // opponent, O, but in the nested round it's X's turn again so they will try to maximize their score.
-                        maximizing_player.opponent(),
                         // Our childs grandparent is out parent. We use negative to normalize the value into our child's
                         // layer again. Every time a score is moved between a layer it has to be normalized like this.
-                        -parents_favourite_child_beta,
+                        if null_window {
+                            -max_value.just_above()
+                        } else {
+                            -parents_favourite_child_beta
+                        },
                         // We are the parent of our child. Normalize the value with the negative sign.
                         -max_value,
                         depth + 1,
                     );
 
+                    // It is better after all, so we need to know by how much.
+                    if null_window
+                        && !self.aborted
+                        && value > max_value
+                        && value < parents_favourite_child_beta
+                    {
+                        self.stats.re_searches += 1;
+                        value = -self.minmax::<P::Enemy>(
+                            board,
+                            -parents_favourite_child_beta,
+                            -max_value,
+                            depth + 1,
+                        );
+                    }
+
                     board.undo_move(pos);
 
+                    if self.aborted {
+                        break;
+                    }
+
                     if value > max_value {
                         max_value = value;
+                        best_move = Some(pos);
                         if depth == 0 {
                             self.best_move = Some(pos);
                         }
+                        if max_value < parents_favourite_child_beta {
+                            self.remember_variation(depth, pos);
+                        }
 
                         // Imagine a game tree like this
                         // The goal of this entire recursion is to find the best play for P (X).
@@ -117,48 +1023,187 @@ impl<G: Game> PerfectPlayer<G> {
                         // max_value for P, so playing B is definitly a very bad idea, no matter the horrors behind D.
                         // So don't even bother calculating the value of D and just break out.
                         if max_value >= parents_favourite_child_beta {
+                            self.stats.beta_cutoffs += 1;
+                            if i == 0 {
+                                self.stats.first_move_cutoffs += 1;
+                            }
+                            self.remember_cutoff(pos, maximizing_player, depth);
                             break;
                         }
                     }
                 }
+                self.move_buffers[depth] = moves;
+
+                if self.aborted {
+                    return max_value;
+                }
+
+                let bound = if max_value <= grandparents_favourite_child_alpha {
+                    Bound::Upper
+                } else if max_value >= parents_favourite_child_beta {
+                    Bound::Lower
+                } else {
+                    Bound::Exact
+                };
+                let reached_depth_limit = self.reached_depth_limit;
+                self.reached_depth_limit |= parent_reached_depth_limit;
+
+                self.table().insert(Entry {
+                    key,
+                    score: max_value.to_table(depth),
+                    bound,
+                    // A score that didn't depend on the depth limit is good enough for every depth.
+                    depth: if reached_depth_limit {
+                        remaining_depth
+                    } else {
+                        u8::MAX
+                    },
+                    best_move: best_move.map(|pos| G::transform_move(pos, symmetry)),
+                });
 
                 max_value
             }
         }
     }
+
+    /// The best move from last time is the most likely to cause a cutoff, so it comes first.
+    /// Then the killer moves, then the rest by their history. Moves that never caused a cutoff
+    /// keep the order of [`Game::possible_moves`]. The root moves after the first keep that order
+    /// too, the parallel search couldn't break ties between equally good moves like the
+    /// sequential one otherwise.
+    /// The moves are in the buffer of the ply, which should be put back once they're searched.
+    fn ordered_moves(
+        &mut self,
+        board: &G,
+        player: Player,
+        depth: usize,
+        table_move: Option<G::Move>,
+    ) -> Vec<G::Move> {
+        let killers = self.killers.get(depth).copied().unwrap_or_default();
+        // Right above the leaves, the children are only rated, and the order of the game already
+        // puts the moves first that `Game::rate` likes. The killers and the history only get in
+        // the way there, that costs more nodes than they save everywhere else.
+        let use_history = self.use_history
+            && depth > 0
+            && !matches!(self.depth_limit, Some(max_depth) if max_depth - depth < 2);
+
+        if self.move_buffers.len() <= depth {
+            self.move_buffers.resize_with(depth + 1, Vec::new);
+        }
+        let mut moves = mem::take(&mut self.move_buffers[depth]);
+        moves.clear();
+        moves.extend(board.possible_moves());
+
+        // The sort is stable, so the order of the game breaks ties.
+        moves.sort_by_key(|&pos| {
+            let priority = if Some(pos) == table_move {
+                u64::MAX
+            } else if !use_history {
+                0
+            } else if Some(pos) == killers[0] {
+                u64::MAX - 1
+            } else if Some(pos) == killers[1] {
+                u64::MAX - 2
+            } else {
+                self.history_score(player, pos)
+            };
+            Reverse(priority)
+        });
+        moves
+    }
+
+    fn history_score(&self, player: Player, pos: G::Move) -> u64 {
+        self.history[Player::as_u8(Some(player)) as usize][G::move_index(pos)]
+    }
+
+    /// [`Game::rate`] plus the evaluation noise of the position.
+    fn noisy_rating(&self, board: &G, player: Player) -> Score<IgnorePlayer> {
+        let score = board.rate(player);
+        if self.eval_noise == 0 {
+            return score;
+        }
+        let mut rng = StdRng::seed_from_u64(self.noise_seed ^ board.canonical_hash().0);
+        score.randomize(&mut rng, self.eval_noise)
+    }
+
+    /// The score of `pos` is exact, so its line is the best one here until a better move comes.
+    fn remember_variation(&mut self, depth: usize, pos: G::Move) {
+        let (lines, child_lines) = self.pv_table.split_at_mut(depth + 1);
+        let line = &mut lines[depth];
+        line.clear();
+        line.push(pos);
+        line.extend_from_slice(&child_lines[0]);
+    }
+
+    fn remember_cutoff(&mut self, pos: G::Move, player: Player, depth: usize) {
+        if self.killers.len() <= depth {
+            self.killers.resize(depth + 1, [None; 2]);
+        }
+        let killers = &mut self.killers[depth];
+        if killers[0] != Some(pos) {
+            killers[1] = killers[0];
+            killers[0] = Some(pos);
+        }
+
+        // Cutoffs close to the root save a lot more work than the ones close to the leaves.
+        let remaining_depth = self.depth_limit.map_or(1, |max_depth| max_depth - depth) as u64;
+        let bonus = remaining_depth * remaining_depth;
+        self.history[Player::as_u8(Some(player)) as usize][G::move_index(pos)] += bonus;
+    }
+
+    fn table(&mut self) -> &mut TranspositionTable<G::Move> {
+        let table_size = self.table_size;
+        self.transposition_table
+            .get_or_insert_with(|| TranspositionTable::new(table_size))
+    }
 }
 
 impl<G: Game> GamePlayer<G> for PerfectPlayer<G> {
-    fn next_move(&mut self, board: &mut G, this_player: Player) {
+    fn next_move(&mut self, board: &G) -> G::Move {
         let start = Instant::now();
-        self.best_move = None;
+        self.start_move();
 
-        // Get the rating for the one move we will make.
-        self.minmax::<GoodPlayer>(
-            board,
-            this_player,
-            Score::LOST.for_player::<GoodPlayer>(),
-            Score::WON.for_player::<GoodPlayer>(),
-            0,
-        );
+        // The search makes and undoes its moves on the board.
+        let board = &mut board.clone();
+        if self.time_limit.is_some() || self.node_limit.is_some() {
+            let deadline = self.time_limit.map(|time_limit| start + time_limit);
+            self.search_iteratively(board, deadline, |_, _| {});
+        } else if self.max_depth.is_none() && !self.scores_every_root_move() {
+            self.search_to_end(board);
+        } else {
+            self.search(board, self.max_depth);
+        }
 
-        board.make_move(self.best_move(board), this_player);
+        self.stats.duration = start.elapsed();
 
         if self.print_time {
-            let duration = start.elapsed();
-            println!("Move took {duration:?}");
+            let duration = self.stats.duration;
+            let score = self.best_score;
+            let pv = self
+                .principal_variation
+                .iter()
+                .map(|&pos| G::format_move(pos))
+                .collect::<Vec<_>>()
+                .join(" ");
+            println!("Move took {duration:?}, score: {score:?}, expected line: {pv}");
+            println!("{}", self.stats);
         }
+
+        self.best_move(board)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::{Duration, Instant};
+
     use crate::assert_win_ratio;
     use crate::connect4::board::Connect4;
-    use crate::minmax::PerfectPlayer;
+    use crate::minmax::{PerfectPlayer, SearchAlgorithm};
 
     use crate::player::{GreedyPlayer, RandomPlayer};
     use crate::tic_tac_toe::TicTacToe;
+    use crate::{Game, GamePlayer, Player, Score, State};
 
     #[test]
     fn perfect_always_beats_greedy() {
@@ -202,4 +1247,435 @@ mod tests {
             || PerfectPlayer::new(false).with_max_depth(Some(5)),
         );
     }
+
+    #[test]
+    fn time_limit_stops_search() {
+        let board = Connect4::empty();
+        let mut player = PerfectPlayer::new(false)
+            .with_max_depth(None)
+            .with_time_limit(Duration::from_millis(100));
+
+        let start = Instant::now();
+        let pos = player.next_move(&board);
+
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(board.possible_moves().any(|legal| legal == pos));
+    }
+
+    #[test]
+    fn node_limit_is_deterministic() {
+        let search = |threads| {
+            let mut player = PerfectPlayer::new(false)
+                .with_max_depth(None)
+                .with_node_limit(50_000)
+                .with_threads(threads);
+            let pos = player.next_move(&Connect4::empty());
+            (pos, player.stats().clone())
+        };
+
+        let (pos, stats) = search(1);
+        assert!(stats.nodes <= 50_000);
+        assert!(stats.max_depth > 5);
+
+        // More threads would split the moves differently every time.
+        for threads in [1, 4] {
+            let (same_pos, same_stats) = search(threads);
+            assert_eq!(same_pos, pos);
+            assert_eq!(same_stats.nodes, stats.nodes);
+            assert_eq!(same_stats.nodes_per_depth, stats.nodes_per_depth);
+        }
+    }
+
+    #[test]
+    fn time_limit_stops_early_when_solved() {
+        let start = Instant::now();
+        assert_win_ratio::<TicTacToe, _, _>(
+            1,
+            1.0,
+            || PerfectPlayer::new(false).with_time_limit(Duration::from_secs(10)),
+            || GreedyPlayer,
+        );
+        assert_win_ratio::<Connect4, _, _>(
+            1,
+            1.0,
+            || {
+                PerfectPlayer::new(false)
+                    .with_max_depth(Some(8))
+                    .with_time_limit(Duration::from_secs(10))
+            },
+            || GreedyPlayer,
+        );
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    fn assert_same_move_with_threads<G: Game>(board: &G, max_depth: Option<usize>) {
+        let mut sequential = PerfectPlayer::new(false).with_max_depth(max_depth);
+        let mut parallel = PerfectPlayer::new(false)
+            .with_max_depth(max_depth)
+            .with_threads(4);
+        // The second time, the tables know the best move and it's searched first.
+        for _ in 0..2 {
+            assert_eq!(
+                sequential.next_move(board),
+                parallel.next_move(board),
+                "different move for\n{board}"
+            );
+        }
+
+        // Iterative deepening tries the best move of the last iteration first.
+        let deepening = |threads| {
+            PerfectPlayer::new(false)
+                .with_max_depth(max_depth)
+                .with_threads(threads)
+                .with_time_limit(Duration::from_secs(60))
+                .next_move(board)
+        };
+        assert_eq!(deepening(1), deepening(4), "different move for\n{board}");
+    }
+
+    #[test]
+    fn threads_find_same_move() {
+        let mut board = TicTacToe::empty();
+        assert_same_move_with_threads(&board, None);
+        board.make_move(4);
+        assert_same_move_with_threads(&board, None);
+
+        let mut board = Connect4::empty();
+        for pos in [3, 3, 2, 4, 4, 1, 6] {
+            assert_same_move_with_threads(&board, Some(7));
+            board.make_move(pos);
+        }
+    }
+
+    #[test]
+    fn threads_share_the_best_score() {
+        let mut board = Connect4::empty();
+        for pos in [3, 3, 2] {
+            board.make_move(pos);
+        }
+
+        let nodes = |threads| {
+            let mut player = PerfectPlayer::new(false)
+                .with_max_depth(Some(11))
+                .with_threads(threads);
+            player.next_move(&board);
+            player.stats().nodes
+        };
+        // Searching every root move with the full window took more than twice as many.
+        let sequential = nodes(1);
+        for threads in [2, 4] {
+            let parallel = nodes(threads);
+            assert!(
+                parallel < sequential * 2,
+                "{parallel} nodes with {threads} threads, {sequential} with one"
+            );
+        }
+    }
+
+    #[test]
+    fn pvs_finds_same_score() {
+        let mut board = Connect4::empty();
+        for pos in [3, 3, 2, 4, 4, 1, 6, 0] {
+            let mut alpha_beta = PerfectPlayer::new(false).with_max_depth(Some(8));
+            alpha_beta.next_move(&board);
+
+            let mut pvs = PerfectPlayer::new(false)
+                .with_max_depth(Some(8))
+                .with_algorithm(SearchAlgorithm::Pvs);
+            pvs.next_move(&board);
+            assert_eq!(pvs.best_score, alpha_beta.best_score, "PVS for\n{board}");
+
+            // Iterative deepening ends with the same depth, but searched with an aspiration window.
+            let mut aspiration = PerfectPlayer::new(false)
+                .with_max_depth(Some(8))
+                .with_algorithm(SearchAlgorithm::Pvs)
+                .with_time_limit(Duration::from_secs(60));
+            aspiration.next_move(&board);
+            assert_eq!(
+                aspiration.best_score, alpha_beta.best_score,
+                "aspiration for\n{board}"
+            );
+
+            board.make_move(pos);
+        }
+    }
+
+    #[test]
+    fn analyze_scores_every_move() {
+        let mut board = Connect4::empty();
+        for pos in [0, 1, 2] {
+            board.make_move(pos);
+            board.make_move(pos);
+        }
+
+        let mut player = PerfectPlayer::new(false).with_max_depth(Some(5));
+        let scores = player.analyze(&board).unwrap();
+
+        assert_eq!(scores.len(), 7);
+        for (pos, score) in scores {
+            if pos == 3 {
+                assert_eq!(score, Score::won_in(1));
+            } else {
+                assert!(score < Score::WON, "column {pos}");
+            }
+        }
+
+        assert_eq!(player.variation(3), [3]);
+        // O has to block.
+        let line = player.variation(6);
+        assert_eq!(line[..2], [6, 3]);
+        assert_eq!(line.len(), 5);
+
+        // Not every move gets a score before the node limit.
+        let mut player = PerfectPlayer::new(false)
+            .with_max_depth(Some(5))
+            .with_node_limit(100);
+        assert_eq!(player.analyze(&board), None);
+    }
+
+    #[test]
+    fn next_move_remembers_principal_variation() {
+        let mut board = Connect4::empty();
+        for pos in [0, 1, 2] {
+            board.make_move(pos);
+            board.make_move(pos);
+        }
+        board.make_move(6);
+
+        // O has to block, then X can't win right away anymore.
+        let mut player = PerfectPlayer::new(false).with_max_depth(Some(5));
+        let pos = player.next_move(&board);
+        board.make_move(pos);
+        let pv = player.principal_variation().to_vec();
+        assert_eq!(pv[0], 3);
+        assert_eq!(pv.len(), 5);
+
+        // Playing the line gets the board the search expected.
+        let mut expected = board.clone();
+        for &pos in &pv[1..] {
+            expected.make_move(pos);
+        }
+        assert_eq!(expected.result(), State::InProgress);
+    }
+
+    #[test]
+    fn variety_picks_between_good_moves() {
+        let first_move = |seed| {
+            PerfectPlayer::new(false)
+                .with_variety(0)
+                .with_seed(seed)
+                .next_move(&TicTacToe::empty())
+        };
+
+        // Every first move is a draw.
+        let mut moves = (0..20).map(first_move).collect::<Vec<_>>();
+        assert_eq!(first_move(7), first_move(7));
+        moves.sort_unstable();
+        moves.dedup();
+        assert!(moves.len() > 1);
+
+        // But there's only one way to not lose here.
+        let mut board = Connect4::empty();
+        for pos in [0, 1, 2] {
+            board.make_move(pos);
+            board.make_move(pos);
+        }
+        board.make_move(6);
+        for seed in 0..5 {
+            let mut player = PerfectPlayer::new(false)
+                .with_max_depth(Some(4))
+                .with_variety(3)
+                .with_seed(seed);
+            player.next_move(&board);
+            assert_eq!(player.best_move, Some(3));
+        }
+    }
+
+    #[test]
+    fn higher_skill_wins() {
+        let mut wins = 0;
+        for seed in 0..5 {
+            let mut strong = PerfectPlayer::new(false).with_skill(8).with_seed(seed);
+            let mut weak = PerfectPlayer::new(false).with_skill(1).with_seed(seed);
+            if Connect4::empty().play(&mut strong, &mut weak) == Some(Player::X) {
+                wins += 1;
+            }
+        }
+        assert!(wins >= 4, "only won {wins} of 5");
+
+        let player = PerfectPlayer::<Connect4>::new(false).with_skill(10);
+        assert_eq!(player.max_depth, Connect4::REASONABLE_SEARCH_DEPTH);
+        assert_eq!(player.blunder_chance, 0.0);
+    }
+
+    #[test]
+    fn seed_gives_same_noise_with_threads() {
+        let mut board = Connect4::empty();
+        for pos in [3, 2, 2, 4] {
+            let scores = |threads| {
+                PerfectPlayer::new(false)
+                    .with_skill(5)
+                    .with_seed(7)
+                    .with_threads(threads)
+                    .analyze(&board)
+                    .unwrap()
+            };
+            assert_eq!(scores(1), scores(4));
+            board.make_move(pos);
+        }
+    }
+
+    #[test]
+    fn tic_tac_toe_is_a_draw() {
+        let scores = PerfectPlayer::new(false)
+            .analyze(&TicTacToe::empty())
+            .unwrap();
+
+        assert_eq!(scores.len(), 9);
+        assert!(scores.iter().all(|(_, score)| *score == Score::TIE));
+    }
+
+    #[test]
+    fn stats_are_counted() {
+        let board = Connect4::empty();
+        let mut player = PerfectPlayer::new(false).with_max_depth(Some(6));
+        player.next_move(&board);
+
+        let stats = player.stats().clone();
+        assert_eq!(stats.max_depth, 6);
+        assert_eq!(stats.nodes_per_depth[0], 1);
+        assert_eq!(stats.nodes_per_depth[1], 7);
+        assert_eq!(stats.nodes, stats.nodes_per_depth.iter().sum::<u64>());
+        assert!(stats.leaf_evaluations > 0);
+        assert!(stats.beta_cutoffs >= stats.first_move_cutoffs);
+
+        // The helpers count too.
+        let mut player = PerfectPlayer::new(false)
+            .with_max_depth(Some(6))
+            .with_threads(3);
+        player.next_move(&board);
+        let stats = player.stats();
+        assert_eq!(stats.nodes_per_depth[..2], [1, 7]);
+        assert_eq!(stats.nodes, stats.nodes_per_depth.iter().sum::<u64>());
+        assert_eq!(stats.max_depth, 6);
+    }
+
+    #[test]
+    fn killers_and_history_order_moves() {
+        let board = Connect4::empty();
+        let mut player = PerfectPlayer::new(false);
+        player.depth_limit = Some(8);
+
+        player.remember_cutoff(6, Player::X, 3);
+        player.remember_cutoff(0, Player::X, 3);
+        player.remember_cutoff(5, Player::X, 1);
+
+        let moves = player.ordered_moves(&board, Player::X, 3, Some(1));
+        assert_eq!(moves, [1, 0, 6, 5, 3, 2, 4]);
+
+        // No killers on this ply, only the history. Equal scores keep the order of the game.
+        let moves = player.ordered_moves(&board, Player::X, 2, None);
+        assert_eq!(moves, [5, 0, 6, 3, 2, 4, 1]);
+
+        // The history is per player.
+        let moves = player.ordered_moves(&board, Player::O, 2, None);
+        assert_eq!(moves, [3, 2, 4, 1, 5, 0, 6]);
+    }
+
+    #[test]
+    fn killers_and_history_save_nodes() {
+        let nodes = |moves: &[usize], use_history| {
+            let mut board = Connect4::empty();
+            for &pos in moves {
+                board.make_move(pos);
+            }
+            let mut player = PerfectPlayer::new(false).with_max_depth(Some(11));
+            player.use_history = use_history;
+            player.next_move(&board);
+            player.stats().nodes
+        };
+
+        for moves in [&[3, 3, 2][..], &[3, 2, 4, 4, 3, 1]] {
+            let (with, without) = (nodes(moves, true), nodes(moves, false));
+            assert!(
+                with * 3 < without * 2,
+                "{with} nodes with them, {without} without after {moves:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn solve_finds_exact_scores() {
+        let mut player = PerfectPlayer::new(false);
+        assert_eq!(player.solve(&TicTacToe::empty()), Some(Score::TIE));
+
+        let mut board = Connect4::empty();
+        for pos in [3, 3, 2, 3] {
+            board.make_move(pos);
+        }
+
+        // Three in a row that is open on both sides.
+        let mut player = PerfectPlayer::new(false);
+        assert_eq!(player.solve(&board), Some(Score::won_in(3)));
+        assert!([1, 4].contains(&player.best_move(&board)));
+
+        board.make_move(4);
+        assert_eq!(player.solve(&board), Some(Score::lost_in(2)));
+    }
+
+    #[test]
+    fn solve_finds_the_whole_winning_line() {
+        let mut board = Connect4::empty();
+        for pos in [3, 2, 2, 4, 4, 5] {
+            board.make_move(pos);
+        }
+
+        for threads in [1, 4] {
+            let mut player = PerfectPlayer::new(false).with_threads(threads);
+            assert_eq!(player.solve(&board), Some(Score::won_in(11)));
+
+            let line = player.principal_variation();
+            assert_eq!(line.len(), 11);
+            assert_eq!(line[0], player.best_move(&board));
+
+            let mut end = board.clone();
+            for &pos in line {
+                assert_eq!(end.result(), State::InProgress);
+                end.make_move(pos);
+            }
+            assert_eq!(end.result(), State::Winner(Player::X));
+        }
+    }
+
+    #[test]
+    fn symmetric_positions_get_equal_scores() {
+        let mut board = Connect4::empty();
+        for pos in [1, 2, 2, 0, 5] {
+            board.make_move(pos);
+        }
+
+        let score = |board: &Connect4| {
+            let mut player = PerfectPlayer::new(false).with_max_depth(Some(8));
+            player.next_move(board);
+            player.best_score
+        };
+        assert_eq!(score(&board), score(&board.transform(1)));
+
+        let mut board = TicTacToe::empty();
+        board.make_move(1);
+        board.make_move(8);
+        let scores = (0..TicTacToe::SYMMETRIES)
+            .map(|symmetry| {
+                let mut player = PerfectPlayer::new(false);
+                player.analyze(&board.transform(symmetry)).unwrap()
+            })
+            .collect::<Vec<_>>();
+        for (symmetry, symmetric_scores) in scores.iter().enumerate() {
+            // Every move has the same score as the matching move on the original board.
+            for &(pos, score) in symmetric_scores {
+                let original = TicTacToe::untransform_move(pos, symmetry);
+                assert!(scores[0].contains(&(original, score)));
+            }
+        }
+    }
 }
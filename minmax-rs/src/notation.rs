@@ -0,0 +1,172 @@
+//! Positions as text, a bit like FEN in chess: the rows from top to bottom separated by `/`,
+//! with `X` and `O` for the pieces and a number for a run of empty positions, then the side to
+//! move. The connect4 board after X played in the middle column is `7/7/7/3X3 O`.
+
+use std::{error::Error, fmt::Display};
+
+use crate::{state::Position, Player};
+
+/// Why [`Game::from_notation`](crate::Game::from_notation) couldn't read a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// The rows or the side to move are missing, or there is more after them.
+    WrongFormat,
+    /// A character that is neither a piece, nor a number of empty positions, nor a `/`.
+    InvalidChar(char),
+    /// The number of rows or of positions in a row doesn't fit the board.
+    WrongSize,
+    /// The side to move is neither `X` nor `O`.
+    InvalidSideToMove,
+    /// X has to have as many pieces as O or one more.
+    ImpossiblePieceCount,
+    /// The pieces say that it's the other player's turn.
+    WrongSideToMove,
+    /// A connect4 piece above an empty position.
+    FloatingPiece,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WrongFormat => f.write_str("expected the rows and the side to move"),
+            Self::InvalidChar(char) => write!(f, "invalid character `{char}`"),
+            Self::WrongSize => f.write_str("the rows don't fit the board"),
+            Self::InvalidSideToMove => f.write_str("the side to move must be X or O"),
+            Self::ImpossiblePieceCount => {
+                f.write_str("X must have as many pieces as O or one more")
+            }
+            Self::WrongSideToMove => f.write_str("the pieces say it's the other player's turn"),
+            Self::FloatingPiece => f.write_str("a piece is above an empty position"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Reads the positions row by row from the top. The side to move has to match the pieces,
+/// the boards know it from them anyway.
+pub(crate) fn parse(
+    notation: &str,
+    width: usize,
+    height: usize,
+) -> Result<Vec<Position>, ParseError> {
+    let mut parts = notation.split_whitespace();
+    let (Some(rows), Some(side_to_move), None) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(ParseError::WrongFormat);
+    };
+
+    let mut positions = Vec::with_capacity(width * height);
+    let rows = rows.split('/').collect::<Vec<_>>();
+    if rows.len() != height {
+        return Err(ParseError::WrongSize);
+    }
+    for row in rows {
+        let row_start = positions.len();
+        for char in row.chars() {
+            match char {
+                'X' | 'x' => positions.push(Some(Player::X)),
+                'O' | 'o' => positions.push(Some(Player::O)),
+                '1'..='9' => {
+                    let empty = char.to_digit(10).expect("checked to be a digit") as usize;
+                    positions.resize(positions.len() + empty, None);
+                }
+                char => return Err(ParseError::InvalidChar(char)),
+            }
+        }
+        if positions.len() - row_start != width {
+            return Err(ParseError::WrongSize);
+        }
+    }
+
+    let side_to_move = match side_to_move {
+        "X" | "x" => Player::X,
+        "O" | "o" => Player::O,
+        _ => return Err(ParseError::InvalidSideToMove),
+    };
+    let count = |player| {
+        positions
+            .iter()
+            .filter(|&&position| position == Some(player))
+            .count()
+    };
+    match Player::to_move(count(Player::X), count(Player::O)) {
+        None => Err(ParseError::ImpossiblePieceCount),
+        Some(player) if player != side_to_move => Err(ParseError::WrongSideToMove),
+        Some(_) => Ok(positions),
+    }
+}
+
+/// Writes the positions, row by row from the top, and the side to move.
+pub(crate) fn write(
+    positions: impl IntoIterator<Item = Position>,
+    width: usize,
+    side_to_move: Player,
+) -> String {
+    let positions = positions.into_iter().collect::<Vec<_>>();
+    let rows = positions.chunks(width).map(|row| {
+        let mut row_notation = String::new();
+        let mut empty = 0;
+        for &position in row {
+            match position {
+                Some(player) => {
+                    if empty > 0 {
+                        row_notation.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    row_notation.push_str(&player.to_string());
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            row_notation.push_str(&empty.to_string());
+        }
+        row_notation
+    });
+
+    let mut notation = rows.collect::<Vec<_>>().join("/");
+    notation.push(' ');
+    notation.push_str(&side_to_move.to_string());
+    notation
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Player;
+
+    use super::{parse, write, ParseError};
+
+    #[test]
+    fn runs_of_empty_positions() {
+        let positions = [
+            None,
+            Some(Player::X),
+            None,
+            None,
+            None,
+            None,
+            Some(Player::O),
+            None,
+        ];
+        let notation = write(positions, 4, Player::X);
+        assert_eq!(notation, "1X2/2O1 X");
+        assert_eq!(parse(&notation, 4, 2), Ok(positions.to_vec()));
+        assert_eq!(parse("1x2/2o1 x", 4, 2), Ok(positions.to_vec()));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse("3/3/3", 3, 3), Err(ParseError::WrongFormat));
+        assert_eq!(parse("3/3/3 X X", 3, 3), Err(ParseError::WrongFormat));
+        assert_eq!(parse("3/3 X", 3, 3), Err(ParseError::WrongSize));
+        assert_eq!(parse("3/4/3 X", 3, 3), Err(ParseError::WrongSize));
+        assert_eq!(parse("3/3/2 X", 3, 3), Err(ParseError::WrongSize));
+        assert_eq!(parse("3/1-1/3 X", 3, 3), Err(ParseError::InvalidChar('-')));
+        assert_eq!(parse("3/3/3 Y", 3, 3), Err(ParseError::InvalidSideToMove));
+        assert_eq!(
+            parse("XX1/3/3 O", 3, 3),
+            Err(ParseError::ImpossiblePieceCount)
+        );
+        assert_eq!(parse("X2/3/3 X", 3, 3), Err(ParseError::WrongSideToMove));
+    }
+}
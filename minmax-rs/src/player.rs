@@ -1,13 +1,49 @@
-use crate::{Game, GamePlayer, Player};
+use std::io::{self, Write};
+
+use crate::{Game, GamePlayer};
 use rand::Rng;
 
+/// Asks for the moves on the terminal, written like [`Game::format_move`] does.
+#[derive(Clone, Default)]
+pub struct HumanPlayer;
+
+impl<G: Game> GamePlayer<G> for HumanPlayer {
+    fn next_move(&mut self, board: &G) -> G::Move {
+        let this_player = board.side_to_move();
+        let mut moves = board
+            .possible_moves()
+            .map(G::format_move)
+            .collect::<Vec<_>>();
+        moves.sort();
+        loop {
+            print!(
+                "{board}where to put the next {this_player}? ({}): ",
+                moves.join(", ")
+            );
+
+            io::stdout().flush().unwrap();
+            let mut buf = String::new();
+            io::stdin().read_line(&mut buf).unwrap();
+
+            match G::parse_move(buf.trim()) {
+                Some(position) => match board.check_move(position) {
+                    Ok(()) => return position,
+                    Err(err) => println!("Invalid move: {err}."),
+                },
+                None => {
+                    println!("Invalid input.")
+                }
+            }
+        }
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct GreedyPlayer;
 
 impl<G: Game> GamePlayer<G> for GreedyPlayer {
-    fn next_move(&mut self, board: &mut G, this_player: Player) {
-        let first_free = board.possible_moves().next().expect("cannot make move");
-        board.make_move(first_free, this_player);
+    fn next_move(&mut self, board: &G) -> G::Move {
+        board.possible_moves().next().expect("cannot make move")
     }
 }
 
@@ -15,10 +51,10 @@ impl<G: Game> GamePlayer<G> for GreedyPlayer {
 pub struct RandomPlayer;
 
 impl<G: Game> GamePlayer<G> for RandomPlayer {
-    fn next_move(&mut self, board: &mut G, this_player: Player) {
+    fn next_move(&mut self, board: &G) -> G::Move {
         let moves = board.possible_moves().collect::<Vec<_>>();
 
         let selected = rand::thread_rng().gen_range(0..moves.len());
-        board.make_move(moves[selected], this_player);
+        moves[selected]
     }
 }
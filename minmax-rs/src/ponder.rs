@@ -0,0 +1,104 @@
+//! Thinking on the opponent's time.
+//! While the opponent is thinking, we search the position with them to move. Whatever they play,
+//! the position after it was part of that search, so it's already in the transposition table.
+
+use std::mem;
+
+use crate::{BackgroundSearch, Game, GamePlayer, PerfectPlayer};
+
+enum State<G: Game> {
+    Idle(Box<PerfectPlayer<G>>),
+    Pondering(BackgroundSearch<G>),
+    /// Only while switching between the other two.
+    Switching,
+}
+
+/// A [`PerfectPlayer`] that keeps searching while the opponent is thinking.
+/// It takes CPU time away from the opponent, so it's only fair against a human.
+pub struct PonderingPlayer<G: Game> {
+    state: State<G>,
+}
+
+impl<G: Game + 'static> PonderingPlayer<G> {
+    pub fn new(player: PerfectPlayer<G>) -> Self {
+        Self {
+            state: State::Idle(Box::new(player)),
+        }
+    }
+
+    /// Stops pondering if needed and returns the player that's searching.
+    fn player(&mut self) -> &mut PerfectPlayer<G> {
+        self.stop_pondering();
+        match &mut self.state {
+            State::Idle(player) => player,
+            State::Pondering(_) | State::Switching => unreachable!("pondering was stopped"),
+        }
+    }
+}
+
+impl<G: Game + 'static> GamePlayer<G> for PonderingPlayer<G> {
+    fn next_move(&mut self, board: &G) -> G::Move {
+        self.player().next_move(board)
+    }
+
+    fn ponder(&mut self, board: &G) {
+        self.stop_pondering();
+        let State::Idle(player) = mem::replace(&mut self.state, State::Switching) else {
+            unreachable!("pondering was stopped");
+        };
+        // Pondering has no time limit, we don't know how long the opponent takes.
+        self.state = State::Pondering(BackgroundSearch::start(*player, board, None));
+    }
+
+    fn stop_pondering(&mut self) {
+        self.state = match mem::replace(&mut self.state, State::Switching) {
+            State::Pondering(search) => State::Idle(Box::new(search.stop())),
+            state => state,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use crate::player::GreedyPlayer;
+    use crate::tic_tac_toe::TicTacToe;
+    use crate::{Game, GamePlayer, PerfectPlayer, State};
+
+    use super::{PonderingPlayer, State as PonderState};
+
+    #[test]
+    fn pondering_fills_the_table() {
+        let mut board = TicTacToe::empty();
+        let mut player = PonderingPlayer::new(PerfectPlayer::new(false));
+        let pos = player.next_move(&board);
+        board.make_move(pos);
+
+        player.ponder(&board);
+        // The search of the whole game tree is quick, let it finish.
+        while !matches!(&player.state, PonderState::Pondering(search) if search.is_finished()) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let pos = GreedyPlayer.next_move(&board);
+        board.make_move(pos);
+        let mut fresh = PerfectPlayer::new(false);
+        fresh.next_move(&board);
+        player.next_move(&board);
+
+        assert!(player.player().stats().nodes < fresh.stats().nodes);
+    }
+
+    #[test]
+    fn plays_whole_games() {
+        let mut board = TicTacToe::empty();
+        let mut x = PonderingPlayer::new(PerfectPlayer::new(false));
+        let mut o = PonderingPlayer::new(PerfectPlayer::new(false));
+
+        assert_eq!(board.play(&mut x, &mut o), None);
+        assert_eq!(board.result(), State::Draw);
+        assert!(matches!(x.state, PonderState::Idle(_)));
+        assert!(matches!(o.state, PonderState::Idle(_)));
+    }
+}
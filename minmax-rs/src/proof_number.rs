@@ -0,0 +1,327 @@
+//! Proof-number search. Instead of a score, it only answers whether one player can force a win,
+//! and it always expands the position that is cheapest to prove or disprove. That finds narrow
+//! forcing lines a lot faster than alpha-beta, which looks at every move up to the same depth.
+//!
+//! Every node needs a proof number, the number of positions that still have to be proven won
+//! to prove this one, and a disproof number, the same for disproving it. A position where the
+//! attacker moves is proven if one move is, and disproven if all moves are. Where the defender
+//! moves, it's the other way around.
+
+use crate::{Game, Player, State};
+
+/// Proof and disproof numbers of positions that are already decided.
+const INFINITY: u32 = u32::MAX;
+
+const DEFAULT_NODE_LIMIT: usize = 1 << 22;
+
+struct Node<M> {
+    /// The move leading to this node, `None` for the root.
+    mv: Option<M>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    attacker_to_move: bool,
+    proof: u32,
+    disproof: u32,
+}
+
+/// The moves that force the win. The attacker has one move in every position,
+/// the defender has all of theirs. The leaves are the won games.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofTree<M> {
+    pub children: Vec<(M, ProofTree<M>)>,
+}
+
+impl<M> ProofTree<M> {
+    /// The number of positions in the tree.
+    pub fn size(&self) -> usize {
+        1 + self
+            .children
+            .iter()
+            .map(|(_, child)| child.size())
+            .sum::<usize>()
+    }
+
+    /// The number of plies of the longest line in the tree.
+    pub fn depth(&self) -> usize {
+        self.children
+            .iter()
+            .map(|(_, child)| child.depth() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofResult<M> {
+    /// The attacker wins, no matter what the defender does.
+    Proven(ProofTree<M>),
+    /// The defender can at least get a draw.
+    Disproven,
+    /// The node limit was reached before the search could tell.
+    Unknown,
+}
+
+pub struct ProofNumberSearch<G: Game> {
+    node_limit: usize,
+    nodes: Vec<Node<G::Move>>,
+}
+
+impl<G: Game> Default for ProofNumberSearch<G> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: Game> ProofNumberSearch<G> {
+    pub fn new() -> Self {
+        Self {
+            node_limit: DEFAULT_NODE_LIMIT,
+            nodes: Vec::new(),
+        }
+    }
+
+    /// The whole tree is kept in memory, so this limits the memory too.
+    pub fn with_node_limit(mut self, node_limit: usize) -> Self {
+        self.node_limit = node_limit;
+        self
+    }
+
+    /// The number of positions in the tree of the last search.
+    pub fn nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Finds out whether `attacker` can force a win on `board`.
+    pub fn prove(&mut self, board: &G, attacker: Player) -> ProofResult<G::Move> {
+        let mut board = board.clone();
+        self.nodes.clear();
+        self.nodes.push(Node {
+            mv: None,
+            parent: None,
+            children: Vec::new(),
+            attacker_to_move: board.side_to_move() == attacker,
+            proof: 1,
+            disproof: 1,
+        });
+        self.evaluate(0, &board, attacker);
+
+        let mut path = Vec::new();
+        while self.nodes[0].proof != 0 && self.nodes[0].disproof != 0 {
+            if self.nodes.len() >= self.node_limit {
+                return ProofResult::Unknown;
+            }
+
+            // Go down to the position that helps the most, and expand it.
+            let mut index = 0;
+            while !self.nodes[index].children.is_empty() {
+                index = self.most_proving_child(index);
+                let mv = self.nodes[index].mv.expect("only the root has no move");
+                board.make_move(mv);
+                path.push(mv);
+            }
+
+            self.expand(index, &mut board, attacker);
+            self.update_ancestors(index);
+
+            for mv in path.drain(..).rev() {
+                board.undo_move(mv);
+            }
+        }
+
+        if self.nodes[0].proof == 0 {
+            ProofResult::Proven(self.proof_tree(0))
+        } else {
+            ProofResult::Disproven
+        }
+    }
+
+    /// The attacker wants to prove, so they go for the smallest proof number.
+    /// The defender goes for the smallest disproof number.
+    fn most_proving_child(&self, index: usize) -> usize {
+        let node = &self.nodes[index];
+        let children = node.children.iter().copied();
+        if node.attacker_to_move {
+            children.min_by_key(|&child| self.nodes[child].proof)
+        } else {
+            children.min_by_key(|&child| self.nodes[child].disproof)
+        }
+        .expect("expanded node without children")
+    }
+
+    /// Finished games are decided right away. Everything else needs one position to be
+    /// proven or disproven, for now.
+    fn evaluate(&mut self, index: usize, board: &G, attacker: Player) {
+        let node = &mut self.nodes[index];
+        (node.proof, node.disproof) = match board.result() {
+            State::Winner(winner) if winner == attacker => (0, INFINITY),
+            // A draw is as good as a win for the defender, the attacker didn't win.
+            State::Winner(_) | State::Draw => (INFINITY, 0),
+            State::InProgress => (1, 1),
+        };
+    }
+
+    fn expand(&mut self, index: usize, board: &mut G, attacker: Player) {
+        let moves = board.possible_moves().collect::<Vec<_>>();
+        for mv in moves {
+            let child = self.nodes.len();
+            self.nodes.push(Node {
+                mv: Some(mv),
+                parent: Some(index),
+                children: Vec::new(),
+                attacker_to_move: board.side_to_move() != attacker,
+                proof: 1,
+                disproof: 1,
+            });
+            board.make_move(mv);
+            self.evaluate(child, board, attacker);
+            board.undo_move(mv);
+            self.nodes[index].children.push(child);
+        }
+    }
+
+    fn update_ancestors(&mut self, mut index: usize) {
+        loop {
+            let node = &self.nodes[index];
+            let children = node.children.iter().map(|&child| &self.nodes[child]);
+            let (proof, disproof) = if node.attacker_to_move {
+                (
+                    children.clone().map(|child| child.proof).min(),
+                    children
+                        .map(|child| child.disproof)
+                        .reduce(u32::saturating_add),
+                )
+            } else {
+                (
+                    children
+                        .clone()
+                        .map(|child| child.proof)
+                        .reduce(u32::saturating_add),
+                    children.map(|child| child.disproof).min(),
+                )
+            };
+
+            let node = &mut self.nodes[index];
+            node.proof = proof.expect("updated node without children");
+            node.disproof = disproof.expect("updated node without children");
+
+            match node.parent {
+                Some(parent) => index = parent,
+                None => return,
+            }
+        }
+    }
+
+    /// Only called on proven nodes.
+    fn proof_tree(&self, index: usize) -> ProofTree<G::Move> {
+        let node = &self.nodes[index];
+        let mut proven = node
+            .children
+            .iter()
+            .filter(|&&child| self.nodes[child].proof == 0);
+
+        let children = if node.attacker_to_move {
+            // Any proven move will do.
+            proven.next().into_iter().collect::<Vec<_>>()
+        } else {
+            proven.collect()
+        };
+
+        ProofTree {
+            children: children
+                .into_iter()
+                .map(|&child| {
+                    let mv = self.nodes[child].mv.expect("only the root has no move");
+                    (mv, self.proof_tree(child))
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        connect4::board::Connect4, tic_tac_toe::TicTacToe, Game, PerfectPlayer, Player, State,
+    };
+
+    use super::{ProofNumberSearch, ProofResult, ProofTree};
+
+    /// Checks that the tree really is a proof: every move is legal, the defender's moves are all
+    /// there and every line ends with a win.
+    fn assert_valid_proof<G: Game>(board: &mut G, attacker: Player, tree: &ProofTree<G::Move>) {
+        if tree.children.is_empty() {
+            assert_eq!(board.result(), State::Winner(attacker), "{board}");
+            return;
+        }
+
+        let moves = board.possible_moves().collect::<Vec<_>>();
+        if board.side_to_move() == attacker {
+            assert_eq!(tree.children.len(), 1);
+        } else {
+            assert_eq!(tree.children.len(), moves.len());
+        }
+
+        for (mv, child) in &tree.children {
+            assert!(moves.contains(mv));
+            board.make_move(*mv);
+            assert_valid_proof(board, attacker, child);
+            board.undo_move(*mv);
+        }
+    }
+
+    #[test]
+    fn proves_forced_win() {
+        let mut board = Connect4::empty();
+        for pos in [3, 3, 2, 3] {
+            board.make_move(pos);
+        }
+
+        let mut search = ProofNumberSearch::new();
+        let ProofResult::Proven(tree) = search.prove(&board, Player::X) else {
+            panic!("X can win here");
+        };
+        assert_valid_proof(&mut board, Player::X, &tree);
+        assert_eq!(tree.depth(), 3);
+
+        // But O can't force a win of their own.
+        assert_eq!(search.prove(&board, Player::O), ProofResult::Disproven);
+    }
+
+    #[test]
+    fn tic_tac_toe_is_no_win() {
+        let mut search = ProofNumberSearch::new();
+        let board = TicTacToe::empty();
+        assert_eq!(search.prove(&board, Player::X), ProofResult::Disproven);
+        assert_eq!(search.prove(&board, Player::O), ProofResult::Disproven);
+
+        let mut search = ProofNumberSearch::new().with_node_limit(10);
+        assert_eq!(search.prove(&board, Player::X), ProofResult::Unknown);
+    }
+
+    #[test]
+    fn agrees_with_perfect_player() {
+        let mut board = Connect4::empty();
+        for pos in [3, 3, 3, 3, 2, 4, 4, 2, 2, 2, 4, 4] {
+            board.make_move(pos);
+        }
+        let current = board.side_to_move();
+
+        let mut search = ProofNumberSearch::new();
+        let mut solver = PerfectPlayer::new(false);
+        for pos in board.possible_moves().collect::<Vec<_>>() {
+            board.make_move(pos);
+            let score = solver.solve(&board).unwrap();
+
+            let proof = search.prove(&board, current);
+            assert_eq!(
+                matches!(proof, ProofResult::Proven(_)),
+                score.plies_to_loss().is_some(),
+                "{board}"
+            );
+            if let ProofResult::Proven(tree) = proof {
+                assert_valid_proof(&mut board, current, &tree);
+            }
+            board.undo_move(pos);
+        }
+    }
+}
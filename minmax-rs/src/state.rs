@@ -6,6 +6,8 @@ use std::{
     ops::{ControlFlow, Neg, Try},
 };
 
+use rand::Rng;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Player {
     X = 1,
@@ -48,6 +50,19 @@ impl Player {
         }
     }
 
+    /// Whose turn it is with this many pieces of each player on the board. X always starts,
+    /// so that's X when both have the same number of pieces and O when X has one more.
+    /// Any other numbers can't come up in a game.
+    pub fn to_move(x_pieces: usize, o_pieces: usize) -> Option<Self> {
+        if x_pieces == o_pieces {
+            Some(Self::X)
+        } else if x_pieces == o_pieces + 1 {
+            Some(Self::O)
+        } else {
+            None
+        }
+    }
+
     pub fn from_u8(num: u8) -> Result<Option<Self>, ()> {
         Ok(match num {
             0 => Some(Player::X),
@@ -120,12 +135,28 @@ impl MinmaxPlayer for IgnorePlayer {
     type Enemy = Self;
 }
 
+/// Won and lost games are this many plies from [`Score::WON`] and [`Score::LOST`] at most.
+/// A lot more than any game takes, but still far away from the scores of [`crate::Game::rate`].
+const MAX_PLIES: i32 = 1 << 16;
+
 impl Score<IgnorePlayer> {
     // Due to the nature of two's completement, we can't actually negate this properly, so add 1.
     pub const LOST: Self = Self(i32::MIN + 1, PhantomData);
     pub const TIE: Self = Self(0, PhantomData);
     pub const WON: Self = Self(i32::MAX, PhantomData);
 
+    /// A game that is won after `plies` more moves. Worse than winning sooner.
+    pub fn won_in(plies: usize) -> Self {
+        debug_assert!(plies < MAX_PLIES as usize);
+        Self(Self::WON.0 - plies as i32, PhantomData)
+    }
+
+    /// A game that is lost after `plies` more moves. Better than losing sooner.
+    pub fn lost_in(plies: usize) -> Self {
+        debug_assert!(plies < MAX_PLIES as usize);
+        Self(Self::LOST.0 + plies as i32, PhantomData)
+    }
+
     pub fn for_player<P>(self) -> Score<P> {
         Score(self.0, PhantomData)
     }
@@ -140,11 +171,62 @@ impl<P> Score<P> {
         Score(self.0, PhantomData)
     }
 
-    #[allow(unused)]
-    fn randomize(self) -> Self {
-        let score = self.0 as f32;
-        let rand = rand::thread_rng();
-        self
+    /// Adds up to `noise` in either direction. Won and lost games stay as they are,
+    /// winning shouldn't look worse than a normal position just because of bad luck.
+    pub fn randomize(self, rng: &mut impl Rng, noise: i32) -> Self {
+        if noise == 0 || self.plies_to_win().is_some() || self.plies_to_loss().is_some() {
+            return self;
+        }
+        Self::new(self.0.saturating_add(rng.gen_range(-noise..=noise)))
+    }
+
+    /// The next better score, for a window that only tells whether a score is better than this one.
+    pub(crate) fn just_above(self) -> Self {
+        Self::new(self.0 + 1)
+    }
+
+    /// The next worse score, for a window that lets this score in.
+    pub(crate) fn just_below(self) -> Self {
+        Self::new(self.0 - 1)
+    }
+
+    /// The number of plies until the game is won, if it's won.
+    pub fn plies_to_win(self) -> Option<usize> {
+        // Could overflow for negative scores in i32.
+        let plies = i64::from(Score::WON.0) - i64::from(self.0);
+        (plies < MAX_PLIES.into()).then_some(plies as usize)
+    }
+
+    /// The number of plies until the game is lost, if it's lost.
+    pub fn plies_to_loss(self) -> Option<usize> {
+        let plies = i64::from(self.0) - i64::from(Score::LOST.0);
+        (plies < MAX_PLIES.into()).then_some(plies as usize)
+    }
+
+    /// Won and lost scores count the plies from the root of the search, but a transposition
+    /// table entry can be used at any ply, so it has to count from its own position instead.
+    pub(crate) fn to_table(self, ply: usize) -> i32 {
+        // Bounds from the window of an ancestor can be won or lost before this ply, so this can
+        // go past WON and LOST. Clamp them, the bound is still correct (if useless).
+        if self.plies_to_win().is_some() {
+            self.0.saturating_add(ply as i32)
+        } else if self.plies_to_loss().is_some() {
+            self.0.saturating_sub(ply as i32).max(Score::LOST.0)
+        } else {
+            self.0
+        }
+    }
+
+    /// The inverse of [`Score::to_table`].
+    pub(crate) fn from_table(score: i32, ply: usize) -> Self {
+        let score = Self::new(score);
+        if score.plies_to_win().is_some() {
+            Self::new(score.0 - ply as i32)
+        } else if score.plies_to_loss().is_some() {
+            Self::new(score.0 + ply as i32)
+        } else {
+            score
+        }
     }
 }
 
@@ -186,11 +268,10 @@ impl<P> Copy for Score<P> {}
 
 impl<P> Debug for Score<P> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // this should be a match but StructuralEq does not like me
-        if self.ignore_side() == Score::WON {
-            f.write_str("WON")
-        } else if Score::LOST == self.ignore_side() {
-            f.write_str("LOST")
+        if let Some(plies) = self.plies_to_win() {
+            write!(f, "WIN in {plies}")
+        } else if let Some(plies) = self.plies_to_loss() {
+            write!(f, "LOSS in {plies}")
         } else {
             Debug::fmt(&self.0, f)
         }
@@ -201,9 +282,11 @@ impl<P> Debug for Score<P> {
 mod tests {
     use std::mem;
 
-    use crate::Player;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{Player, Score};
 
-    use super::Position;
+    use super::{GoodPlayer, IgnorePlayer, Position};
 
     #[test]
     fn position_size_and_repr() {
@@ -218,4 +301,46 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn distance_to_end() {
+        assert!(Score::won_in(1) > Score::won_in(5));
+        assert!(Score::lost_in(5) > Score::lost_in(1));
+        assert!(Score::lost_in(100) < Score::new(-1000));
+        assert_eq!(
+            -Score::won_in(3).for_player::<GoodPlayer>(),
+            Score::lost_in(3).for_player()
+        );
+
+        assert_eq!(format!("{:?}", Score::won_in(5)), "WIN in 5");
+        assert_eq!(format!("{:?}", Score::lost_in(3)), "LOSS in 3");
+        assert_eq!(format!("{:?}", Score::<IgnorePlayer>::new(-7)), "-7");
+    }
+
+    #[test]
+    fn table_scores_count_from_position() {
+        // Won 5 plies from the root, found at ply 2, so it's won in 3 from the position.
+        let score = Score::won_in(5);
+        let stored = score.to_table(2);
+        assert_eq!(stored, Score::won_in(3).0);
+        // Used again at ply 4, so it's won in 7 from the root there.
+        assert_eq!(Score::from_table(stored, 4), Score::won_in(7));
+
+        let score = Score::lost_in(5);
+        assert_eq!(Score::from_table(score.to_table(2), 2), score);
+        assert_eq!(Score::<IgnorePlayer>::from_table(12, 2), Score::new(12));
+    }
+
+    #[test]
+    fn randomize_keeps_won_and_lost() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for _ in 0..100 {
+            let score = Score::<IgnorePlayer>::new(10).randomize(&mut rng, 3);
+            assert!((7..=13).contains(&score.0));
+        }
+        assert_eq!(Score::won_in(3).randomize(&mut rng, 3), Score::won_in(3));
+        assert_eq!(Score::lost_in(3).randomize(&mut rng, 3), Score::lost_in(3));
+        assert_eq!(Score::<IgnorePlayer>::new(5).randomize(&mut rng, 0).0, 5);
+    }
 }
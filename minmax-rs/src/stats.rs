@@ -0,0 +1,126 @@
+//! Numbers about a search, to compare changes to the engine with something better than a stopwatch.
+
+use std::{fmt::Display, time::Duration};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchStats {
+    /// Every position that was searched, including leaves and finished games.
+    pub nodes: u64,
+    /// Positions that were cut off by the depth limit and rated with [`crate::Game::rate`].
+    pub leaf_evaluations: u64,
+    /// Positions where the transposition table already knew enough to not search them again.
+    pub table_hits: u64,
+    /// Positions where a move was so good that the other moves didn't have to be searched.
+    pub beta_cutoffs: u64,
+    /// Beta cutoffs caused by the first move that was searched. The more the better the move ordering.
+    pub first_move_cutoffs: u64,
+    /// Searches that had to be repeated with a wider window, see [`crate::SearchAlgorithm::Pvs`].
+    pub re_searches: u64,
+    /// The deepest ply that was searched.
+    pub max_depth: usize,
+    /// The number of nodes at every ply, starting with the root.
+    pub nodes_per_depth: Vec<u64>,
+    pub duration: Duration,
+}
+
+impl SearchStats {
+    pub(crate) fn count_node(&mut self, depth: usize) {
+        self.nodes += 1;
+        self.max_depth = self.max_depth.max(depth);
+        if self.nodes_per_depth.len() <= depth {
+            self.nodes_per_depth.resize(depth + 1, 0);
+        }
+        self.nodes_per_depth[depth] += 1;
+    }
+
+    /// Adds the numbers of a search that ran at the same time, like on another thread.
+    pub(crate) fn merge(&mut self, other: &SearchStats) {
+        self.nodes += other.nodes;
+        self.leaf_evaluations += other.leaf_evaluations;
+        self.table_hits += other.table_hits;
+        self.beta_cutoffs += other.beta_cutoffs;
+        self.first_move_cutoffs += other.first_move_cutoffs;
+        self.re_searches += other.re_searches;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        if self.nodes_per_depth.len() < other.nodes_per_depth.len() {
+            self.nodes_per_depth.resize(other.nodes_per_depth.len(), 0);
+        }
+        for (nodes, other) in self.nodes_per_depth.iter_mut().zip(&other.nodes_per_depth) {
+            *nodes += other;
+        }
+    }
+
+    /// The share of beta cutoffs that were caused by the first move, between 0 and 1.
+    pub fn first_move_cutoff_rate(&self) -> f64 {
+        if self.beta_cutoffs == 0 {
+            return 0.0;
+        }
+        self.first_move_cutoffs as f64 / self.beta_cutoffs as f64
+    }
+
+    /// 0 if the search was too quick to measure.
+    pub fn nodes_per_second(&self) -> f64 {
+        if self.duration.is_zero() {
+            return 0.0;
+        }
+        self.nodes as f64 / self.duration.as_secs_f64()
+    }
+
+    /// The number of children a node would need to have on average to get a tree with
+    /// as many nodes as we searched in a search as deep as ours.
+    pub fn effective_branching_factor(&self) -> f64 {
+        if self.max_depth == 0 {
+            return 0.0;
+        }
+        (self.nodes as f64).powf(1.0 / self.max_depth as f64)
+    }
+}
+
+impl Display for SearchStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "nodes: {}, nps: {:.0}, depth: {}, leaves: {}, table hits: {}, cutoffs: {} ({:.1}% first move), re-searches: {}, ebf: {:.2}",
+            self.nodes,
+            self.nodes_per_second(),
+            self.max_depth,
+            self.leaf_evaluations,
+            self.table_hits,
+            self.beta_cutoffs,
+            self.first_move_cutoff_rate() * 100.0,
+            self.re_searches,
+            self.effective_branching_factor(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SearchStats;
+
+    #[test]
+    fn count_and_merge() {
+        let mut a = SearchStats::default();
+        a.count_node(0);
+        a.count_node(1);
+        a.count_node(1);
+
+        let mut b = SearchStats::default();
+        b.count_node(2);
+        b.beta_cutoffs = 2;
+        b.first_move_cutoffs = 1;
+
+        a.merge(&b);
+        assert_eq!(a.nodes, 4);
+        assert_eq!(a.max_depth, 2);
+        assert_eq!(a.nodes_per_depth, [1, 2, 1]);
+        assert_eq!(a.first_move_cutoff_rate(), 0.5);
+        assert_eq!(a.effective_branching_factor(), 2.0);
+        assert_eq!(a.nodes_per_second(), 0.0);
+
+        a.duration = Duration::from_millis(500);
+        assert_eq!(a.nodes_per_second(), 8.0);
+    }
+}
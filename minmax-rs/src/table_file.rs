@@ -0,0 +1,135 @@
+//! The file format of the opening book and the database: tables from position keys to one byte.
+//!
+//! A file starts with four magic bytes and a version, then the number of entries as a little
+//! endian `u32`. Every entry is its little endian `u64` key followed by the value as a byte.
+//! The entries are sorted by key and every key is only there once.
+
+use std::io::{self, Read, Write};
+
+/// What sets the files of one kind of table apart.
+pub(crate) struct TableFormat {
+    pub magic: &'static [u8; 4],
+    pub version: u8,
+    /// For the error messages.
+    pub name: &'static str,
+}
+
+impl TableFormat {
+    pub fn write(
+        &self,
+        mut writer: impl Write,
+        entries: impl ExactSizeIterator<Item = (u64, u8)>,
+    ) -> io::Result<()> {
+        writer.write_all(self.magic)?;
+        writer.write_all(&[self.version])?;
+        writer.write_all(&(entries.len() as u32).to_le_bytes())?;
+        for (key, value) in entries {
+            writer.write_all(&key.to_le_bytes())?;
+            writer.write_all(&[value])?;
+        }
+        writer.flush()
+    }
+
+    /// Reads the entries written by [`TableFormat::write`]. `decode` turns a byte back into a
+    /// value, or rejects it with `None`.
+    pub fn read<V>(
+        &self,
+        mut reader: impl Read,
+        decode: impl Fn(u8) -> Option<V>,
+    ) -> io::Result<Vec<(u64, V)>> {
+        let invalid = |message| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} {message}", self.name),
+            )
+        };
+
+        let mut header = [0; 5];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != self.magic {
+            return Err(invalid("has the wrong magic bytes"));
+        }
+        if header[4] != self.version {
+            return Err(invalid("has an unsupported version"));
+        }
+
+        let mut len = [0; 4];
+        reader.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len);
+
+        // The length could be anything, so the entries are only allocated once they're there.
+        let mut entries = Vec::new();
+        let mut last_key = None;
+        for _ in 0..len {
+            let mut key = [0; 8];
+            let mut value = [0; 1];
+            reader.read_exact(&mut key)?;
+            reader.read_exact(&mut value)?;
+
+            let key = u64::from_le_bytes(key);
+            if last_key >= Some(key) {
+                return Err(invalid("entries are not sorted"));
+            }
+            last_key = Some(key);
+            let value = decode(value[0]).ok_or_else(|| invalid("has an invalid value"))?;
+            entries.push((key, value));
+        }
+        if reader.read(&mut [0])? != 0 {
+            return Err(invalid("has trailing bytes"));
+        }
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TableFormat;
+
+    const FORMAT: TableFormat = TableFormat {
+        magic: b"TEST",
+        version: 1,
+        name: "test table",
+    };
+
+    fn read(bytes: &[u8]) -> std::io::Result<Vec<(u64, u8)>> {
+        FORMAT.read(bytes, |value| (value < 10).then_some(value))
+    }
+
+    #[test]
+    fn round_trip() {
+        let entries = [(1, 2), (5, 0), (u64::MAX, 9)];
+        let mut bytes = Vec::new();
+        FORMAT.write(&mut bytes, entries.into_iter()).unwrap();
+        assert_eq!(bytes.len(), 4 + 1 + 4 + entries.len() * 9);
+        assert_eq!(read(&bytes).unwrap(), entries);
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        let write = |entries: &[(u64, u8)]| {
+            let mut bytes = Vec::new();
+            FORMAT.write(&mut bytes, entries.iter().copied()).unwrap();
+            bytes
+        };
+
+        let bytes = write(&[(1, 2), (5, 0)]);
+        assert!(read(&bytes[..bytes.len() - 1]).is_err());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(read(&trailing).is_err());
+        let mut other_magic = bytes.clone();
+        other_magic[0] = b'X';
+        assert!(read(&other_magic).is_err());
+        let mut other_version = bytes;
+        other_version[4] = 2;
+        assert!(read(&other_version).is_err());
+
+        assert!(read(&write(&[(5, 0), (1, 2)])).is_err());
+        assert!(read(&write(&[(1, 2), (1, 3)])).is_err());
+        assert!(read(&write(&[(1, 10)])).is_err());
+
+        // A huge length doesn't make it allocate anything up front.
+        assert!(read(b"TEST\x01\xff\xff\xff\xff").is_err());
+    }
+}
@@ -1,20 +1,44 @@
 use std::fmt::{Display, Write};
 
-use crate::{Game, Player, Score, State, state::IgnorePlayer};
+use crate::{Game, MoveError, ParseError, Player, Score, State, notation, state::IgnorePlayer};
 
 #[derive(Clone)]
-pub struct TicTacToe(u32);
+pub struct TicTacToe {
+    /// The board with each of the [`SYMMETRIES`] applied, all updated together so that the
+    /// canonical hash doesn't need to transform the board. The first one is the board itself.
+    symmetric: [u32; SYMMETRIES.len()],
+}
+
+/// Where every position ends up with each of the symmetries of the square.
+const SYMMETRIES: [[usize; 9]; 8] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8],
+    // Rotated by 90, 180 and 270 degrees clockwise.
+    [2, 5, 8, 1, 4, 7, 0, 3, 6],
+    [8, 7, 6, 5, 4, 3, 2, 1, 0],
+    [6, 3, 0, 7, 4, 1, 8, 5, 2],
+    // Mirrored from left to right, from top to bottom, and along both diagonals.
+    [2, 1, 0, 5, 4, 3, 8, 7, 6],
+    [6, 7, 8, 3, 4, 5, 0, 1, 2],
+    [0, 3, 6, 1, 4, 7, 2, 5, 8],
+    [8, 5, 2, 7, 4, 1, 6, 3, 0],
+];
 
 impl TicTacToe {
     pub fn empty() -> Self {
         // A = 1010
         // 18 bits - 9 * 2 bits - 4.5 nibbles
-        Self(0x0002AAAA)
+        Self {
+            symmetric: [0x0002AAAA; SYMMETRIES.len()],
+        }
+    }
+
+    fn bits(&self) -> u32 {
+        self.symmetric[0]
     }
 
     fn validate(&self) {
         if cfg!(debug_assertions) {
-            let board = self.0;
+            let board = self.bits();
             for i in 0..16 {
                 let next_step = board >> (i * 2);
                 let mask = 0b11;
@@ -29,7 +53,7 @@ impl TicTacToe {
     pub fn get(&self, index: usize) -> Option<Player> {
         debug_assert!(index < 9);
 
-        let board = self.0;
+        let board = self.bits();
 
         let shifted = board >> (index * 2);
         let masked = shifted & 0b11;
@@ -49,12 +73,15 @@ impl TicTacToe {
 
         let value = Player::as_u8(value) as u32;
 
-        let value = value << (index * 2);
-        let mask = 0b11 << (index * 2);
+        for (board, symmetry) in self.symmetric.iter_mut().zip(&SYMMETRIES) {
+            let index = symmetry[index];
+            let value = value << (index * 2);
+            let mask = 0b11 << (index * 2);
 
-        let current_masked_off_new = self.0 & !mask;
-        let result = value | current_masked_off_new;
-        self.0 = result;
+            let current_masked_off_new = *board & !mask;
+            let result = value | current_masked_off_new;
+            *board = result;
+        }
 
         self.validate();
     }
@@ -83,9 +110,9 @@ mod win_table {
         include_bytes!(concat!(env!("OUT_DIR"), "/win_table"));
 
     pub fn result(board: &TicTacToe) -> State {
-        match WIN_TABLE[board.0 as usize] {
+        match WIN_TABLE[board.bits() as usize] {
             0 => State::Winner(Player::X),
-            1 => State::Winner(Player::X),
+            1 => State::Winner(Player::O),
             2 => State::InProgress,
             3 => State::Draw,
             n => panic!("Invalid value {n} in table"),
@@ -103,7 +130,7 @@ impl Display for TicTacToe {
                         write!(f, "\x1B[33m{player}\x1B[0m  ")?;
                     }
                     None => {
-                        write!(f, "\x1B[35m{index}\x1B[0m  ")?;
+                        write!(f, "\x1B[35m{}\x1B[0m  ", index + 1)?;
                     }
                 }
             }
@@ -118,10 +145,50 @@ impl Game for TicTacToe {
 
     const REASONABLE_SEARCH_DEPTH: Option<usize> = None;
 
+    const MOVE_COUNT: usize = 9;
+
+    fn move_index(position: Self::Move) -> usize {
+        position
+    }
+
     fn empty() -> Self {
         Self::empty()
     }
 
+    fn from_notation(notation: &str) -> Result<Self, ParseError> {
+        let positions = notation::parse(notation, 3, 3)?;
+        let mut board = Self::empty();
+        for (position, value) in positions.into_iter().enumerate() {
+            board.set(position, value);
+        }
+        Ok(board)
+    }
+
+    fn to_notation(&self) -> String {
+        notation::write(self.iter(), 3, self.side_to_move())
+    }
+
+    fn parse_move(text: &str) -> Option<Self::Move> {
+        // The numbers are the ones on the board, counting from 1 at the top left.
+        if let Ok(position) = text.parse::<usize>() {
+            return position.checked_sub(1);
+        }
+        let mut chars = text.chars();
+        let (Some(column), Some(row), None) = (chars.next(), chars.next(), chars.next()) else {
+            return None;
+        };
+        let column = "abc".find(column.to_ascii_lowercase())?;
+        let row = "321".find(row)?;
+        Some(row * 3 + column)
+    }
+
+    fn format_move(position: Self::Move) -> String {
+        // Like in chess, row 1 is at the bottom.
+        let column = char::from(b'a' + (position % 3) as u8);
+        let row = 3 - position / 3;
+        format!("{column}{row}")
+    }
+
     fn possible_moves(&self) -> impl Iterator<Item = Self::Move> {
         debug_assert!(
             !self.iter().all(|x| x.is_some()),
@@ -147,18 +214,88 @@ impl Game for TicTacToe {
         }
     }
 
-    fn make_move(&mut self, position: Self::Move, player: Player) {
-        self.set(position, Some(player));
+    fn side_to_move(&self) -> Player {
+        // With 9 positions, counting is as fast as keeping track.
+        let pieces = self.iter().filter(Option::is_some).count();
+        if pieces % 2 == 0 {
+            Player::X
+        } else {
+            Player::O
+        }
+    }
+
+    fn valid_side_to_move(&self) -> Option<Player> {
+        let count = |player| {
+            self.iter()
+                .filter(|&position| position == Some(player))
+                .count()
+        };
+        Player::to_move(count(Player::X), count(Player::O))
+    }
+
+    fn make_move(&mut self, position: Self::Move) {
+        debug_assert!(self.get(position).is_none(), "{position} is occupied");
+        self.set(position, Some(self.side_to_move()));
+    }
+
+    fn check_move(&self, position: Self::Move) -> Result<(), MoveError> {
+        if position >= 9 {
+            Err(MoveError::OutOfRange)
+        } else if self.result() != State::InProgress {
+            Err(MoveError::GameOver)
+        } else if self.get(position).is_some() {
+            Err(MoveError::Occupied)
+        } else {
+            Ok(())
+        }
     }
 
     fn undo_move(&mut self, position: Self::Move) {
         self.set(position, None);
     }
+
+    fn hash(&self) -> u64 {
+        // The board already is a unique 18 bit number that changes with every move.
+        u64::from(self.bits())
+    }
+
+    const SYMMETRIES: usize = SYMMETRIES.len();
+
+    fn transform(&self, symmetry: usize) -> Self {
+        let mut board = Self::empty();
+        for (position, value) in self.iter().enumerate() {
+            board.set(SYMMETRIES[symmetry][position], value);
+        }
+        board
+    }
+
+    fn transform_move(position: Self::Move, symmetry: usize) -> Self::Move {
+        SYMMETRIES[symmetry][position]
+    }
+
+    fn untransform_move(position: Self::Move, symmetry: usize) -> Self::Move {
+        SYMMETRIES[symmetry]
+            .iter()
+            .position(|&transformed| transformed == position)
+            .expect("every symmetry moves every position somewhere")
+    }
+
+    fn canonical_hash(&self) -> (u64, usize) {
+        // The first of the smallest, like `Game::canonical`.
+        let (symmetry, &board) = self
+            .symmetric
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, &board)| board)
+            .expect("there is always the symmetry that does nothing");
+        (u64::from(board), symmetry)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{Player, TicTacToe};
+    use crate::{Game, MoveError, ParseError, State};
 
     #[test]
     fn board_field() {
@@ -186,4 +323,137 @@ mod tests {
             .enumerate()
             .for_each(|(idx, (actual, expected))| assert_eq!(actual, expected, "Position {idx}"));
     }
+
+    #[test]
+    fn winner() {
+        for player in [Player::X, Player::O] {
+            let mut board = TicTacToe::empty();
+            board.set(2, Some(player));
+            board.set(4, Some(player));
+            assert_eq!(board.result(), State::InProgress);
+
+            board.set(6, Some(player));
+            assert_eq!(board.result(), State::Winner(player));
+        }
+    }
+
+    #[test]
+    fn symmetries() {
+        let mut board = TicTacToe::empty();
+        board.make_move(0);
+        board.make_move(1);
+
+        let mut canonical_hashes = Vec::new();
+        for symmetry in 0..TicTacToe::SYMMETRIES {
+            let transformed = board.transform(symmetry);
+
+            // Moves do the same thing on the transformed board.
+            let mut after_move = board.clone();
+            after_move.make_move(5);
+            let mut transformed_after_move = transformed.clone();
+            let pos = TicTacToe::transform_move(5, symmetry);
+            transformed_after_move.make_move(pos);
+            assert_eq!(
+                after_move.transform(symmetry).hash(),
+                transformed_after_move.hash()
+            );
+            assert_eq!(TicTacToe::untransform_move(pos, symmetry), 5);
+
+            canonical_hashes.push(transformed.canonical_hash().0);
+        }
+        canonical_hashes.dedup();
+        assert_eq!(canonical_hashes, [board.canonical().0.hash()]);
+    }
+
+    #[test]
+    fn symmetric_boards_follow_the_moves() {
+        let mut board = TicTacToe::empty();
+        let moves = [4, 0, 8, 2, 1];
+        for &pos in &moves {
+            board.make_move(pos);
+            for symmetry in 0..TicTacToe::SYMMETRIES {
+                assert_eq!(board.symmetric[symmetry], board.transform(symmetry).bits());
+            }
+            let (canonical, symmetry) = board.canonical();
+            assert_eq!(board.canonical_hash(), (canonical.hash(), symmetry));
+        }
+
+        for &pos in moves.iter().rev() {
+            board.undo_move(pos);
+        }
+        assert_eq!(board.symmetric, TicTacToe::empty().symmetric);
+    }
+
+    #[test]
+    fn illegal_moves() {
+        let mut board = TicTacToe::empty();
+        board.make_move(4);
+        assert_eq!(board.check_move(4), Err(MoveError::Occupied));
+        assert_eq!(board.check_move(9), Err(MoveError::OutOfRange));
+        assert!(board.is_legal(0));
+
+        assert_eq!(board.try_make_move(4), Err(MoveError::Occupied));
+        assert_eq!(board.get(4), Some(Player::X));
+
+        for pos in [1, 0, 2, 8] {
+            board.make_move(pos);
+        }
+        assert_eq!(board.result(), State::Winner(Player::X));
+        assert_eq!(board.check_move(3), Err(MoveError::GameOver));
+    }
+
+    #[test]
+    fn side_to_move_follows_the_pieces() {
+        let mut board = TicTacToe::empty();
+        assert_eq!(board.side_to_move(), Player::X);
+        board.make_move(4);
+        assert_eq!(board.side_to_move(), Player::O);
+        board.make_move(0);
+        assert_eq!(board.get(0), Some(Player::O));
+        assert_eq!(board.valid_side_to_move(), Some(Player::X));
+
+        board.set(8, Some(Player::O));
+        assert_eq!(board.valid_side_to_move(), None);
+    }
+
+    #[test]
+    fn notation_round_trip() {
+        let mut board = TicTacToe::empty();
+        assert_eq!(board.to_notation(), "3/3/3 X");
+
+        for pos in [4, 0, 8] {
+            board.make_move(pos);
+        }
+        let notation = board.to_notation();
+        assert_eq!(notation, "O2/1X1/2X O");
+
+        let parsed = TicTacToe::from_notation(&notation).unwrap();
+        assert_eq!(parsed.hash(), board.hash());
+        assert_eq!(parsed.side_to_move(), Player::O);
+        assert_eq!(parsed.to_notation(), notation);
+
+        assert_eq!(
+            TicTacToe::from_notation("O2/1X1/2X X").err(),
+            Some(ParseError::WrongSideToMove)
+        );
+    }
+
+    #[test]
+    fn move_notation() {
+        assert_eq!(TicTacToe::format_move(0), "a3");
+        assert_eq!(TicTacToe::format_move(4), "b2");
+        assert_eq!(TicTacToe::format_move(8), "c1");
+        for position in 0..9 {
+            let text = TicTacToe::format_move(position);
+            assert_eq!(TicTacToe::parse_move(&text), Some(position), "{text}");
+        }
+
+        assert_eq!(TicTacToe::parse_move("B2"), Some(4));
+        assert_eq!(TicTacToe::parse_move("5"), TicTacToe::parse_move("b2"));
+        assert_eq!(TicTacToe::parse_move("1"), Some(0));
+        assert_eq!(TicTacToe::parse_move("0"), None);
+        assert_eq!(TicTacToe::parse_move("d1"), None);
+        assert_eq!(TicTacToe::parse_move("a4"), None);
+        assert_eq!(TicTacToe::parse_move("b2 "), None);
+    }
 }
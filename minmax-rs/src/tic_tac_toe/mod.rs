@@ -1,5 +1,3 @@
 mod board;
-mod game;
-mod player;
 
-pub use {board::TicTacToe, player::*};
+pub use board::TicTacToe;
@@ -0,0 +1,110 @@
+//! A fixed size transposition table.
+//! Many positions can be reached through different orders of moves, so we remember
+//! what we found out about a position to avoid searching it again.
+
+use crate::Player;
+
+/// XORed into the key when O is to move so that the same board with a different
+/// player to move doesn't end up sharing an entry.
+const O_TO_MOVE: u64 = 0x9E37_79B9_7F4A_7C15;
+
+pub fn key(hash: u64, player: Player) -> u64 {
+    match player {
+        Player::X => hash,
+        Player::O => hash ^ O_TO_MOVE,
+    }
+}
+
+/// What the stored score tells us about the real value of the position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is the real value.
+    Exact,
+    /// The search failed high, the real value is at least the score.
+    Lower,
+    /// The search failed low, the real value is at most the score.
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entry<M> {
+    pub key: u64,
+    pub score: i32,
+    pub bound: Bound,
+    /// The remaining depth that the position was searched with.
+    pub depth: u8,
+    pub best_move: Option<M>,
+}
+
+#[derive(Clone)]
+pub struct TranspositionTable<M> {
+    entries: Vec<Option<Entry<M>>>,
+}
+
+impl<M: Copy> TranspositionTable<M> {
+    /// Creates a new table with room for at least `size` entries.
+    pub fn new(size: usize) -> Self {
+        Self {
+            entries: vec![None; size.max(1).next_power_of_two()],
+        }
+    }
+
+    fn index(&self, key: u64) -> usize {
+        // The length is always a power of two, so this is a cheap modulo.
+        (key as usize) & (self.entries.len() - 1)
+    }
+
+    pub fn get(&self, key: u64) -> Option<&Entry<M>> {
+        self.entries[self.index(key)]
+            .as_ref()
+            .filter(|entry| entry.key == key)
+    }
+
+    pub fn insert(&mut self, entry: Entry<M>) {
+        let index = self.index(entry.key);
+        let slot = &mut self.entries[index];
+
+        // Always replace other positions, but don't throw away a deeper search of the same position.
+        match slot {
+            Some(existing) if existing.key == entry.key && existing.depth > entry.depth => {}
+            _ => *slot = Some(entry),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bound, Entry, TranspositionTable};
+
+    fn entry(key: u64, depth: u8, score: i32) -> Entry<usize> {
+        Entry {
+            key,
+            score,
+            bound: Bound::Exact,
+            depth,
+            best_move: Some(3),
+        }
+    }
+
+    #[test]
+    fn get_checks_key() {
+        let mut table = TranspositionTable::new(16);
+        table.insert(entry(5, 1, 10));
+
+        assert_eq!(table.get(5).map(|entry| entry.score), Some(10));
+        // Same slot, different position.
+        assert!(table.get(5 + 16).is_none());
+    }
+
+    #[test]
+    fn deeper_entry_is_kept() {
+        let mut table = TranspositionTable::new(16);
+        table.insert(entry(5, 4, 10));
+        table.insert(entry(5, 2, 20));
+        assert_eq!(table.get(5).map(|entry| entry.score), Some(10));
+
+        table.insert(entry(5 + 16, 1, 30));
+        assert!(table.get(5).is_none());
+        assert_eq!(table.get(5 + 16).map(|entry| entry.score), Some(30));
+    }
+}
@@ -1,8 +1,8 @@
 use jni::objects::{JClass, JObject, ReleaseMode};
 use jni::sys::{jbyte, jint};
 use jni::JNIEnv;
-use minmax::{connect4::board::Connect4, GamePlayer};
-use minmax::{Game, PerfectPlayer, Player, State};
+use minmax::{connect4::board::Connect4, Game, GamePlayer};
+use minmax::{PerfectPlayer, Player, State};
 
 /// We need to map the board.
 /// Rust:
@@ -62,20 +62,25 @@ unsafe fn create_board(env: JNIEnv<'_>, board: JObject<'_>) -> Connect4 {
 // 0 -> RED -> X
 // 1 -> BLUE -> O
 // 2 -> empty
+// Returns -1 instead of a move if it's not the turn of `current_player` on the board,
+// panicking would take the whole JVM down.
 pub fn play_move(env: JNIEnv<'_>, current_player: i8, board: JObject<'_>) -> i32 {
-    let mut board = unsafe { create_board(env, board) };
+    let board = unsafe { create_board(env, board) };
 
     let mut player = PerfectPlayer::new(false);
 
     let current_player_rust = match current_player {
         0 => Player::X,
         1 => Player::O,
-        _ => unreachable!(),
+        _ => return -1,
     };
 
-    player.next_move(&mut board, current_player_rust);
-    let result_move = player.best_move(&board);
-    board.undo_move(result_move);
+    // The board knows whose turn it is from the pieces, Java only tells us to double check.
+    if board.valid_side_to_move() != Some(current_player_rust) {
+        return -1;
+    }
+
+    let result_move = player.next_move(&board);
 
     let result_move = board.drop_player(result_move);
 